@@ -0,0 +1,87 @@
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::index::SearchIndex;
+use crate::links::SymlinkPolicy;
+use crate::listing::ListingFormat;
+use crate::search::Limits;
+use crate::site::StaticSite;
+use crate::templates::Templates;
+use crate::visibility::Visibility;
+
+/// Everything a request handler needs besides the request itself. It is
+/// built once at startup and shared by all connections.
+pub struct App {
+    /// The absolute path of the directory being served, with symlinks
+    /// resolved so that it matches the resolved paths of entries below it.
+    pub(crate) root: PathBuf,
+    /// Shared with response bodies that keep rendering after the handler returns.
+    pub(crate) templates: Arc<Templates>,
+    pub(crate) search: Limits,
+    /// The full-text index, when one was asked for.
+    pub(crate) index: Option<Arc<SearchIndex>>,
+    pub(crate) index_interval: Duration,
+    /// Which entries are hidden from clients.
+    pub(crate) visibility: Visibility,
+    pub(crate) symlinks: SymlinkPolicy,
+    /// Whether listings leave out the absolute path of the directory.
+    pub(crate) hide_path: bool,
+    /// The number of rows on a page of a listing.
+    pub(crate) page_size: usize,
+    /// The listing format served when the request does not ask for one.
+    pub(crate) listing_format: ListingFormat,
+    /// Set when the root is served as a static web site.
+    pub(crate) site: Option<StaticSite>,
+}
+
+impl App {
+    pub fn new(config: Config) -> io::Result<App> {
+        let root = match config.root {
+            Some(root) => std::path::absolute(root)?,
+            None => std::env::current_dir()?,
+        };
+        if !root.is_dir() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} is not a directory", root.display())));
+        }
+        let root = root.canonicalize()?;
+        let search = Limits {
+            max_depth: config.search_depth,
+            max_results: config.search_limit,
+            timeout: config.search_timeout,
+        };
+        let visibility = Visibility { show_hidden: config.show_hidden, use_gitignore: config.gitignore };
+        let site = match config.spa_fallback {
+            Some(fallback) if !root.join(&fallback).is_file() => {
+                let message = format!("the SPA fallback {} is not a file below the root", fallback.display());
+                return Err(io::Error::new(io::ErrorKind::NotFound, message));
+            }
+            // A fallback only makes sense for a site, so it turns the mode on by itself.
+            fallback => (config.static_site || fallback.is_some())
+                .then_some(StaticSite { index_files: config.index_files, fallback }),
+        };
+        let index = match &config.index {
+            Some(state_dir) => {
+                let index = Arc::new(SearchIndex::open(&root, state_dir, visibility, config.symlinks)?);
+                index.spawn_updater(config.index_interval);
+                Some(index)
+            }
+            None => None,
+        };
+        Ok(App {
+            root,
+            templates: Arc::new(Templates::new(config.templates.as_deref())?),
+            search,
+            index,
+            index_interval: config.index_interval,
+            visibility,
+            symlinks: config.symlinks,
+            hide_path: config.hide_path,
+            page_size: config.page_size,
+            listing_format: if config.autoindex { ListingFormat::Autoindex } else { ListingFormat::Html },
+            site,
+        })
+    }
+}
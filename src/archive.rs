@@ -0,0 +1,384 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use flate2::read::{DeflateDecoder, GzDecoder};
+use zip::CompressionMethod;
+
+use crate::format;
+use crate::vfs::{FileSystem, FileType, Metadata};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl Format {
+    fn detect(path: &Path) -> Option<Format> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        if name.ends_with(".zip") {
+            Some(Format::Zip)
+        } else if name.ends_with(".tar") {
+            Some(Format::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Format::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Where a member's bytes live inside the archive file.
+#[derive(Debug, Clone, Copy)]
+enum Location {
+    /// Zip members are located by the offset of their (possibly deflated)
+    /// data in the archive file.
+    Zip { data_start: u64, compressed_size: u64, deflated: bool },
+    /// Tar members are located by their offset in the uncompressed stream.
+    Tar { data_start: u64 },
+}
+
+#[derive(Debug, Clone)]
+struct Member {
+    file_type: FileType,
+    size: u64,
+    modified: Option<SystemTime>,
+    permissions: Option<u32>,
+    location: Option<Location>,
+}
+
+/// Returns whether `path` names an archive that can be browsed like a directory.
+pub fn is_archive(path: &Path) -> bool {
+    Format::detect(path).is_some()
+}
+
+/// Exposes the contents of a zip or tar archive as a read-only filesystem.
+///
+/// Members appear under the archive's own path, so `build.zip/logs/a.txt`
+/// is the member `logs/a.txt`. The index is built once when the archive is
+/// opened; member contents are decompressed on demand as they are read.
+pub struct ArchiveFs<'a> {
+    source: &'a dyn FileSystem,
+    archive: PathBuf,
+    format: Format,
+    members: BTreeMap<PathBuf, Member>,
+}
+
+impl<'a> ArchiveFs<'a> {
+    pub fn open(source: &'a dyn FileSystem, archive: &Path) -> io::Result<ArchiveFs<'a>> {
+        let format = Format::detect(archive)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not an archive", archive.display())))?;
+        let mut archive_fs = ArchiveFs { source, archive: archive.to_path_buf(), format, members: BTreeMap::new() };
+        match format {
+            Format::Zip => archive_fs.index_zip()?,
+            Format::Tar | Format::TarGz => archive_fs.index_tar()?,
+        }
+        Ok(archive_fs)
+    }
+
+    fn index_zip(&mut self) -> io::Result<()> {
+        let mut zip = zip::ZipArchive::new(self.source.open_seekable(&self.archive)?).map_err(io::Error::other)?;
+        for index in 0..zip.len() {
+            let file = zip.by_index_raw(index).map_err(io::Error::other)?;
+            let Some(name) = file.enclosed_name() else { continue };
+            let modified = file.last_modified().map(|time| {
+                format::from_civil(
+                    time.year() as i64,
+                    time.month() as u32,
+                    time.day() as u32,
+                    time.hour() as u32,
+                    time.minute() as u32,
+                    time.second() as u32,
+                )
+            });
+            let permissions = file.unix_mode().map(|mode| mode & 0o777);
+            if file.is_dir() {
+                self.insert_dir(&name, modified, permissions);
+                continue;
+            }
+            let deflated = match file.compression() {
+                CompressionMethod::Stored => false,
+                CompressionMethod::Deflated => true,
+                // Members we cannot decompress are still listed, but not served.
+                _ => {
+                    self.insert_file(&name, file.size(), modified, permissions, None);
+                    continue;
+                }
+            };
+            let location = file.data_start().map(|data_start| Location::Zip {
+                data_start,
+                compressed_size: file.compressed_size(),
+                deflated,
+            });
+            self.insert_file(&name, file.size(), modified, permissions, location);
+        }
+        Ok(())
+    }
+
+    fn index_tar(&mut self) -> io::Result<()> {
+        let mut tar = tar::Archive::new(self.open_stream()?);
+        for entry in tar.entries()? {
+            let entry = entry?;
+            let Some(name) = entry.path().ok().and_then(|path| sanitize(&path)) else { continue };
+            let modified = entry.header().mtime().ok().map(|mtime| UNIX_EPOCH + Duration::from_secs(mtime));
+            let permissions = entry.header().mode().ok().map(|mode| mode & 0o777);
+            match entry.header().entry_type() {
+                tar::EntryType::Directory => self.insert_dir(&name, modified, permissions),
+                tar::EntryType::Regular | tar::EntryType::Continuous => {
+                    let location = Location::Tar { data_start: entry.raw_file_position() };
+                    self.insert_file(&name, entry.size(), modified, permissions, Some(location));
+                }
+                // Links and special files have no contents of their own to serve.
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Opens the uncompressed byte stream of a tar archive.
+    fn open_stream(&self) -> io::Result<Box<dyn Read + Send>> {
+        let raw = self.source.open(&self.archive)?;
+        Ok(match self.format {
+            Format::TarGz => Box::new(GzDecoder::new(raw)),
+            _ => raw,
+        })
+    }
+
+    /// Records a directory along with any ancestors the archive left implicit.
+    fn insert_dir(&mut self, name: &Path, modified: Option<SystemTime>, permissions: Option<u32>) {
+        for ancestor in name.ancestors().filter(|ancestor| !ancestor.as_os_str().is_empty()) {
+            let member = self.members.entry(ancestor.to_path_buf()).or_insert(Member {
+                file_type: FileType::Dir,
+                size: 0,
+                modified: None,
+                permissions: None,
+                location: None,
+            });
+            if ancestor == name {
+                member.modified = modified.or(member.modified);
+                member.permissions = permissions.or(member.permissions);
+            }
+        }
+    }
+
+    fn insert_file(
+        &mut self,
+        name: &Path,
+        size: u64,
+        modified: Option<SystemTime>,
+        permissions: Option<u32>,
+        location: Option<Location>,
+    ) {
+        if let Some(parent) = name.parent() {
+            self.insert_dir(parent, None, None);
+        }
+        let member = Member { file_type: FileType::File, size, modified, permissions, location };
+        self.members.insert(name.to_path_buf(), member);
+    }
+
+    /// Maps a full path below the archive to the member name inside it.
+    fn member_name<'p>(&self, path: &'p Path) -> io::Result<&'p Path> {
+        path.strip_prefix(&self.archive).map_err(|_| not_found(path))
+    }
+
+    fn member(&self, path: &Path) -> io::Result<Member> {
+        let name = self.member_name(path)?;
+        if name.as_os_str().is_empty() {
+            return Ok(Member { file_type: FileType::Dir, size: 0, modified: None, permissions: None, location: None });
+        }
+        self.members.get(name).cloned().ok_or_else(|| not_found(path))
+    }
+}
+
+/// Paths outside the archive, such as the ignore files of the directories
+/// holding it, are looked up in the filesystem holding the archive.
+impl FileSystem for ArchiveFs<'_> {
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        if !path.starts_with(&self.archive) {
+            return self.source.metadata(path);
+        }
+        let member = self.member(path)?;
+        Ok(Metadata {
+            file_type: member.file_type,
+            len: member.size,
+            modified: member.modified,
+            permissions: member.permissions,
+        })
+    }
+
+    fn read_dir<'s>(&'s self, path: &Path) -> io::Result<Box<dyn Iterator<Item = PathBuf> + 's>> {
+        if self.member(path)?.file_type != FileType::Dir {
+            return Err(io::Error::new(io::ErrorKind::NotADirectory, format!("{} is not a directory", path.display())));
+        }
+        let name = self.member_name(path)?.to_path_buf();
+        Ok(Box::new(
+            self.members
+                .keys()
+                .filter(move |candidate| candidate.parent() == Some(name.as_path()))
+                .map(|candidate| self.archive.join(candidate)),
+        ))
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        if !path.starts_with(&self.archive) {
+            return self.source.open(path);
+        }
+        let member = self.member(path)?;
+        if member.file_type == FileType::Dir {
+            return Err(io::Error::new(io::ErrorKind::IsADirectory, format!("{} is a directory", path.display())));
+        }
+        match member.location {
+            Some(Location::Zip { data_start, compressed_size, deflated }) => {
+                let mut raw = self.source.open_seekable(&self.archive)?;
+                raw.seek(SeekFrom::Start(data_start))?;
+                let compressed = raw.take(compressed_size);
+                Ok(if deflated { Box::new(DeflateDecoder::new(compressed).take(member.size)) } else { Box::new(compressed) })
+            }
+            Some(Location::Tar { data_start }) => {
+                let mut stream = self.open_stream()?;
+                io::copy(&mut stream.by_ref().take(data_start), &mut io::sink())?;
+                Ok(Box::new(stream.take(member.size)))
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{} uses an unsupported compression method", path.display()),
+            )),
+        }
+    }
+
+    /// Members are never links, so paths inside the archive are already
+    /// canonical; anything else is resolved by the filesystem holding it.
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        if path.starts_with(&self.archive) { Ok(path.to_path_buf()) } else { self.source.canonicalize(path) }
+    }
+}
+
+/// Turns an archive member name into a relative path, dropping anything that
+/// could escape the archive root.
+fn sanitize(path: &Path) -> Option<PathBuf> {
+    let mut sanitized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => sanitized.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    (!sanitized.as_os_str().is_empty()).then_some(sanitized)
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{} not found", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::{Cursor, Write};
+    use zip::write::SimpleFileOptions;
+
+    fn read_member(fs: &dyn FileSystem, path: &str) -> String {
+        let mut contents = String::new();
+        fs.open(Path::new(path)).unwrap().read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    fn tar_bytes() -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, contents) in [("logs/build.log", "compiling"), ("./README.txt", "read me")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, contents.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_is_archive() {
+        assert!(is_archive(Path::new("build.zip")));
+        assert!(is_archive(Path::new("build.TAR.GZ")));
+        assert!(is_archive(Path::new("build.tgz")));
+        assert!(is_archive(Path::new("build.tar")));
+        assert!(!is_archive(Path::new("build.gz")));
+        assert!(!is_archive(Path::new("zip")));
+    }
+
+    #[test]
+    fn test_sanitize_rejects_escaping_names() {
+        assert_eq!(sanitize(Path::new("./a/b")), Some(PathBuf::from("a/b")));
+        assert_eq!(sanitize(Path::new("../etc/passwd")), None);
+        assert_eq!(sanitize(Path::new("/etc/passwd")), None);
+        assert_eq!(sanitize(Path::new(".")), None);
+    }
+
+    #[test]
+    fn test_zip_members_are_listed_and_streamed() {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        writer.start_file("logs/build.log", stored).unwrap();
+        writer.write_all(b"compiling").unwrap();
+        writer.add_directory("empty/", stored).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let source = MemoryFs::new();
+        source.add_file("/srv/build.zip", bytes);
+        let archive = ArchiveFs::open(&source, Path::new("/srv/build.zip")).unwrap();
+
+        let mut root = archive.read_dir(Path::new("/srv/build.zip")).unwrap().collect::<Vec<_>>();
+        root.sort();
+        assert_eq!(root, vec![PathBuf::from("/srv/build.zip/empty"), PathBuf::from("/srv/build.zip/logs")]);
+        assert!(archive.metadata(Path::new("/srv/build.zip/logs")).unwrap().is_dir());
+        assert!(archive.metadata(Path::new("/srv/build.zip/logs/build.log")).unwrap().is_file());
+        assert_eq!(read_member(&archive, "/srv/build.zip/logs/build.log"), "compiling");
+        assert!(archive.metadata(Path::new("/srv/build.zip/missing")).is_err());
+    }
+
+    #[test]
+    fn test_deflated_zip_member() {
+        let contents = "deflate me ".repeat(100);
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        writer.start_file("notes.txt", deflated).unwrap();
+        writer.write_all(contents.as_bytes()).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let source = MemoryFs::new();
+        source.add_file("/srv/notes.zip", bytes);
+        let archive = ArchiveFs::open(&source, Path::new("/srv/notes.zip")).unwrap();
+        assert_eq!(read_member(&archive, "/srv/notes.zip/notes.txt"), contents);
+    }
+
+    #[test]
+    fn test_tar_members_are_listed_and_streamed() {
+        let source = MemoryFs::new();
+        source.add_file("/srv/build.tar", tar_bytes());
+        let archive = ArchiveFs::open(&source, Path::new("/srv/build.tar")).unwrap();
+
+        let mut root = archive.read_dir(Path::new("/srv/build.tar")).unwrap().collect::<Vec<_>>();
+        root.sort();
+        assert_eq!(root, vec![PathBuf::from("/srv/build.tar/README.txt"), PathBuf::from("/srv/build.tar/logs")]);
+        assert_eq!(read_member(&archive, "/srv/build.tar/logs/build.log"), "compiling");
+        assert_eq!(read_member(&archive, "/srv/build.tar/README.txt"), "read me");
+    }
+
+    #[test]
+    fn test_tar_gz_members_are_streamed() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&tar_bytes()).unwrap();
+        let source = MemoryFs::new();
+        source.add_file("/srv/build.tar.gz", encoder.finish().unwrap());
+        let archive = ArchiveFs::open(&source, Path::new("/srv/build.tar.gz")).unwrap();
+
+        assert_eq!(read_member(&archive, "/srv/build.tar.gz/README.txt"), "read me");
+        assert_eq!(read_member(&archive, "/srv/build.tar.gz/logs/build.log"), "compiling");
+        assert!(archive.open(Path::new("/srv/build.tar.gz/logs")).is_err());
+    }
+}
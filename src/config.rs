@@ -0,0 +1,261 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::links::SymlinkPolicy;
+use crate::site::DEFAULT_INDEX_FILES;
+
+pub const USAGE: &str = "Usage: RustWebExplorer [OPTIONS]
+
+Serves a directory (by default the current one) over HTTP.
+
+Options:
+    --root DIR         Serve DIR instead of the current working directory
+    --bind ADDR        Listen on ADDR [default: 127.0.0.1:8080]
+    --templates DIR    Load HTML templates from DIR, overriding the built-in ones
+    --search-depth N   Descend at most N directories when searching [default: 16]
+    --search-limit N   Stop a search after N results [default: 500]
+    --search-timeout SECS
+                       Stop a search after SECS seconds [default: 5]
+    --index DIR        Keep a full-text index of the served files in DIR
+    --index-interval SECS
+                       Rescan for changed files every SECS seconds [default: 60]
+    --show-hidden      List and serve dotfiles such as .git and .env, which are
+                       hidden by default
+    --gitignore        Hide what .gitignore files ignore, as well as what
+                       .rweignore files do
+    --symlinks POLICY  Follow symlinks that stay inside the root (inside), that
+                       lead anywhere (anywhere), or none at all (never)
+                       [default: inside]
+    --hide-path        Never show the served directory's location on the server
+    --page-size N      Show N entries per page of a listing [default: 500]
+    --autoindex        List directories the way Apache's mod_autoindex does,
+                       for mirroring tools; ?format=html still gives the full page
+    --static           Serve the root as a web site: a directory holding an index
+                       file shows that file instead of a listing
+    --index-files NAMES
+                       Comma-separated index file names for --static
+                       [default: index.html,index.htm]
+    --spa-fallback FILE
+                       Serve FILE, relative to the root, for paths that do not
+                       exist; implies --static
+    -h, --help         Print this help";
+
+/// Settings chosen on the command line.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The directory being served; the working directory when unset.
+    pub root: Option<PathBuf>,
+    /// The address to listen on. Port 0 picks a free port.
+    pub bind: String,
+    /// Directory of templates that replace the built-in ones.
+    pub templates: Option<PathBuf>,
+    /// How many directories below the starting one a search may descend.
+    pub search_depth: usize,
+    /// How many results a search collects before it stops.
+    pub search_limit: usize,
+    /// How long a search may walk the tree before it stops.
+    pub search_timeout: Duration,
+    /// State directory for the full-text index; no index is kept when unset.
+    pub index: Option<PathBuf>,
+    /// How long the index waits between scans for changed files.
+    pub index_interval: Duration,
+    /// Whether names starting with '.' are listed and served.
+    pub show_hidden: bool,
+    /// Whether `.gitignore` files hide entries like `.rweignore` files do.
+    pub gitignore: bool,
+    /// Which symlinks are followed.
+    pub symlinks: SymlinkPolicy,
+    /// Whether listings leave out the absolute path of the directory.
+    pub hide_path: bool,
+    /// How many rows a page of a directory listing holds unless `?limit=`
+    /// asks for another number.
+    pub page_size: usize,
+    /// Whether plain directory requests get Apache-style listings.
+    pub autoindex: bool,
+    /// Whether directories with an index file serve it instead of a listing.
+    pub static_site: bool,
+    /// The index file names looked for in static site mode.
+    pub index_files: Vec<String>,
+    /// The file, relative to the root, served for unknown paths; setting
+    /// it turns on static site mode.
+    pub spa_fallback: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            root: None,
+            bind: "127.0.0.1:8080".to_string(),
+            templates: None,
+            search_depth: 16,
+            search_limit: 500,
+            search_timeout: Duration::from_secs(5),
+            index: None,
+            index_interval: Duration::from_secs(60),
+            show_hidden: false,
+            gitignore: false,
+            symlinks: SymlinkPolicy::Inside,
+            hide_path: false,
+            page_size: 500,
+            autoindex: false,
+            static_site: false,
+            index_files: DEFAULT_INDEX_FILES.map(str::to_string).to_vec(),
+            spa_fallback: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Config, String> {
+        let mut config = Config::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--root" => config.root = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--bind" => config.bind = value(&mut args, &arg)?,
+                "--templates" => config.templates = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--search-depth" => config.search_depth = number(&mut args, &arg)?,
+                "--search-limit" => config.search_limit = number(&mut args, &arg)?,
+                "--search-timeout" => config.search_timeout = Duration::from_secs(number(&mut args, &arg)?),
+                "--index" => config.index = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--index-interval" => config.index_interval = Duration::from_secs(number(&mut args, &arg)?),
+                "--show-hidden" => config.show_hidden = true,
+                "--gitignore" => config.gitignore = true,
+                "--symlinks" => {
+                    let policy = value(&mut args, &arg)?;
+                    config.symlinks = SymlinkPolicy::parse(&policy)
+                        .ok_or_else(|| format!("--symlinks expects inside, anywhere or never, not '{}'", policy))?;
+                }
+                "--hide-path" => config.hide_path = true,
+                "--page-size" => match number(&mut args, &arg)? {
+                    0 => return Err("--page-size must be at least 1".to_string()),
+                    size => config.page_size = size,
+                },
+                "--autoindex" => config.autoindex = true,
+                "--static" => config.static_site = true,
+                "--index-files" => {
+                    let names = value(&mut args, &arg)?;
+                    config.index_files =
+                        names.split(',').map(str::trim).filter(|name| !name.is_empty()).map(str::to_string).collect();
+                    if config.index_files.is_empty() {
+                        return Err("--index-files needs at least one name".to_string());
+                    }
+                }
+                "--spa-fallback" => config.spa_fallback = Some(PathBuf::from(value(&mut args, &arg)?)),
+                _ => return Err(format!("unknown argument '{}'", arg)),
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("{} requires a value", flag))
+}
+
+fn number<T: std::str::FromStr>(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<T, String> {
+    let value = value(args, flag)?;
+    value.parse().map_err(|_| format!("{} expects a number, not '{}'", flag, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config, String> {
+        Config::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_from_args_defaults() {
+        let config = parse(&[]).unwrap();
+        assert!(config.root.is_none());
+        assert_eq!(config.bind, "127.0.0.1:8080");
+        assert!(config.templates.is_none());
+        assert_eq!(config.search_depth, 16);
+        assert_eq!(config.search_limit, 500);
+        assert_eq!(config.search_timeout, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_from_args_root_and_bind() {
+        let config = parse(&["--root", "/srv/www", "--bind", "0.0.0.0:0"]).unwrap();
+        assert_eq!(config.root, Some(PathBuf::from("/srv/www")));
+        assert_eq!(config.bind, "0.0.0.0:0");
+    }
+
+    #[test]
+    fn test_from_args_templates() {
+        assert_eq!(parse(&["--templates", "theme"]).unwrap().templates, Some(PathBuf::from("theme")));
+        assert!(parse(&["--templates"]).is_err());
+    }
+
+    #[test]
+    fn test_from_args_search_limits() {
+        let config = parse(&["--search-depth", "3", "--search-limit", "20", "--search-timeout", "1"]).unwrap();
+        assert_eq!(config.search_depth, 3);
+        assert_eq!(config.search_limit, 20);
+        assert_eq!(config.search_timeout, Duration::from_secs(1));
+        assert_eq!(parse(&["--search-depth", "deep"]).unwrap_err(), "--search-depth expects a number, not 'deep'");
+    }
+
+    #[test]
+    fn test_from_args_index() {
+        assert!(parse(&[]).unwrap().index.is_none());
+        let config = parse(&["--index", "/var/lib/rwe", "--index-interval", "600"]).unwrap();
+        assert_eq!(config.index, Some(PathBuf::from("/var/lib/rwe")));
+        assert_eq!(config.index_interval, Duration::from_secs(600));
+    }
+
+    #[test]
+    fn test_from_args_visibility() {
+        let config = parse(&[]).unwrap();
+        assert!(!config.show_hidden && !config.gitignore);
+        let config = parse(&["--show-hidden", "--gitignore"]).unwrap();
+        assert!(config.show_hidden && config.gitignore);
+    }
+
+    #[test]
+    fn test_from_args_symlinks() {
+        assert_eq!(parse(&[]).unwrap().symlinks, SymlinkPolicy::Inside);
+        assert_eq!(parse(&["--symlinks", "never"]).unwrap().symlinks, SymlinkPolicy::Never);
+        assert_eq!(parse(&["--symlinks", "all"]).unwrap_err(), "--symlinks expects inside, anywhere or never, not 'all'");
+    }
+
+    #[test]
+    fn test_from_args_static_site() {
+        let config = parse(&[]).unwrap();
+        assert!(!config.static_site);
+        assert_eq!(config.index_files, ["index.html", "index.htm"]);
+        let config = parse(&["--static", "--index-files", "home.html, default.htm"]).unwrap();
+        assert!(config.static_site);
+        assert_eq!(config.index_files, ["home.html", "default.htm"]);
+        let config = parse(&["--spa-fallback", "app.html"]).unwrap();
+        assert_eq!(config.spa_fallback, Some(PathBuf::from("app.html")));
+        assert_eq!(parse(&["--index-files", ","]).unwrap_err(), "--index-files needs at least one name");
+    }
+
+    #[test]
+    fn test_from_args_hide_path() {
+        assert!(!parse(&[]).unwrap().hide_path);
+        assert!(parse(&["--hide-path"]).unwrap().hide_path);
+    }
+
+    #[test]
+    fn test_from_args_page_size() {
+        assert_eq!(parse(&[]).unwrap().page_size, 500);
+        assert_eq!(parse(&["--page-size", "50"]).unwrap().page_size, 50);
+        assert_eq!(parse(&["--page-size", "0"]).unwrap_err(), "--page-size must be at least 1");
+    }
+
+    #[test]
+    fn test_from_args_autoindex() {
+        assert!(!parse(&[]).unwrap().autoindex);
+        assert!(parse(&["--autoindex"]).unwrap().autoindex);
+    }
+
+    #[test]
+    fn test_from_args_rejects_unknown() {
+        assert_eq!(parse(&["--verbose"]).unwrap_err(), "unknown argument '--verbose'");
+    }
+}
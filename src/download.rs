@@ -0,0 +1,503 @@
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use zip::write::{FullFileOptions, StreamWriter};
+use zip::{CompressionMethod, DateTime, ZipWriter};
+
+use crate::format;
+use crate::search::Walk;
+use crate::vfs::{FileSystem, Metadata};
+
+/// How much of a file is packed per read of the response body.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Files from this size on get ZIP64 headers up front. The margin below
+/// 4 GiB covers compressed data coming out slightly larger than its input
+/// and files that grow while they are read.
+const LARGE_FILE: u64 = 1 << 31;
+
+/// The size of a tar block, which entries are padded to.
+const BLOCK_SIZE: u64 = 512;
+
+/// The zip extra field holding a Unix modification time, which unzip tools
+/// prefer over the DOS date since it is exact and in UTC.
+const EXTENDED_TIMESTAMP: u16 = 0x5455;
+
+/// Where an archive writer puts its output until the response body collects it.
+#[derive(Clone, Default)]
+struct Sink(Arc<Mutex<Vec<u8>>>);
+
+impl Sink {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The archive formats a directory can be downloaded as, chosen with
+/// `?archive=`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+    #[cfg(feature = "zstd")]
+    TarZst,
+}
+
+impl ArchiveFormat {
+    /// The format named by an `?archive=` value, if it is one this build offers.
+    pub fn parse(name: &str) -> Option<ArchiveFormat> {
+        Some(match name {
+            "zip" => ArchiveFormat::Zip,
+            "tar" => ArchiveFormat::Tar,
+            "tar.gz" | "tgz" => ArchiveFormat::TarGz,
+            #[cfg(feature = "zstd")]
+            "tar.zst" => ArchiveFormat::TarZst,
+            _ => return None,
+        })
+    }
+
+    /// The file name extension downloads are offered with.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::TarGz => "tar.gz",
+            #[cfg(feature = "zstd")]
+            ArchiveFormat::TarZst => "tar.zst",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "application/zip",
+            ArchiveFormat::Tar => "application/x-tar",
+            ArchiveFormat::TarGz => "application/gzip",
+            #[cfg(feature = "zstd")]
+            ArchiveFormat::TarZst => "application/zstd",
+        }
+    }
+
+    fn packer(self, sink: Sink) -> io::Result<Box<dyn Packer>> {
+        let compressor = match self {
+            ArchiveFormat::Zip => return Ok(Box::new(ZipWriter::new_stream(sink).set_auto_large_file())),
+            ArchiveFormat::Tar => Compressor::Plain(sink),
+            ArchiveFormat::TarGz => Compressor::Gzip(GzEncoder::new(sink, Compression::default())),
+            #[cfg(feature = "zstd")]
+            ArchiveFormat::TarZst => Compressor::Zstd(zstd::Encoder::new(sink, 0)?),
+        };
+        Ok(Box::new(TarPacker { builder: tar::Builder::new(compressor), size: 0, remaining: 0 }))
+    }
+}
+
+/// A response body that packs the tree a [`Walk`] reaches into an archive
+/// as it is read, a chunk at a time, so nothing is staged on disk and only
+/// one chunk is held in memory. Directories are stored too, so empty ones
+/// survive, and every entry keeps its modification time and permissions.
+/// Tar archives store symlinks within the walked directory as links; zip
+/// archives hold what they point to.
+pub struct ArchiveStream<F> {
+    fs: F,
+    walk: Walk,
+    sink: Sink,
+    /// None once the archive has been finished.
+    packer: Option<Box<dyn Packer>>,
+    /// The file being packed.
+    file: Option<Box<dyn Read + Send>>,
+    chunk: Vec<u8>,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl<F: FileSystem> ArchiveStream<F> {
+    pub fn new(fs: F, walk: Walk, format: ArchiveFormat) -> io::Result<ArchiveStream<F>> {
+        let sink = Sink::default();
+        Ok(ArchiveStream {
+            fs,
+            walk: if format == ArchiveFormat::Zip { walk } else { walk.keeping_links() },
+            packer: Some(format.packer(sink.clone())?),
+            sink,
+            file: None,
+            chunk: vec![0; CHUNK_SIZE],
+            buffer: Vec::new(),
+            position: 0,
+        })
+    }
+
+    /// Packs the next chunk of the current file, or starts the next entry,
+    /// or finishes the archive once the walk is over. Returns false when the
+    /// archive is complete.
+    fn fill(&mut self) -> io::Result<bool> {
+        let Some(packer) = self.packer.as_mut() else { return Ok(false) };
+        if let Some(file) = self.file.as_mut() {
+            match file.read(&mut self.chunk)? {
+                0 => {
+                    self.file = None;
+                    packer.end_file()?;
+                }
+                len => packer.write_file(&self.chunk[..len])?,
+            }
+        } else if let Some(step) = self.walk.next_step(&self.fs) {
+            let name = step.relative.to_string_lossy().replace('\\', "/");
+            if let Some(target) = &step.link {
+                packer.add_link(&name, target, &step.metadata)?;
+            } else if step.metadata.is_dir() {
+                packer.add_dir(&name, &step.metadata)?;
+            } else if let Ok(file) = self.fs.open(&step.path) {
+                // Files that cannot be opened are left out rather than failing the download.
+                packer.start_file(&name, &step.metadata)?;
+                self.file = Some(file);
+            }
+        } else if let Some(packer) = self.packer.take() {
+            packer.finish()?;
+        }
+        self.buffer = self.sink.take();
+        self.position = 0;
+        Ok(true)
+    }
+}
+
+impl<F: FileSystem> Read for ArchiveStream<F> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.buffer.len() {
+            if !self.fill()? {
+                return Ok(0);
+            }
+        }
+        let len = out.len().min(self.buffer.len() - self.position);
+        out[..len].copy_from_slice(&self.buffer[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+/// Writes the entries of one archive format. Names use '/' and are relative
+/// to the downloaded directory.
+trait Packer: Send {
+    fn add_dir(&mut self, name: &str, metadata: &Metadata) -> io::Result<()>;
+    fn add_link(&mut self, name: &str, target: &Path, metadata: &Metadata) -> io::Result<()>;
+    /// Starts a file whose contents follow through `write_file`.
+    fn start_file(&mut self, name: &str, metadata: &Metadata) -> io::Result<()>;
+    fn write_file(&mut self, bytes: &[u8]) -> io::Result<()>;
+    fn end_file(&mut self) -> io::Result<()>;
+    /// Writes whatever closes the archive.
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+impl Packer for ZipWriter<StreamWriter<Sink>> {
+    fn add_dir(&mut self, name: &str, metadata: &Metadata) -> io::Result<()> {
+        Ok(self.add_directory(name, entry_options(metadata))?)
+    }
+
+    fn add_link(&mut self, name: &str, target: &Path, metadata: &Metadata) -> io::Result<()> {
+        let options = entry_options(metadata).large_file(false).unix_permissions(0o777);
+        Ok(self.add_symlink(name, target.to_string_lossy(), options)?)
+    }
+
+    fn start_file(&mut self, name: &str, metadata: &Metadata) -> io::Result<()> {
+        Ok(ZipWriter::start_file(self, name, entry_options(metadata))?)
+    }
+
+    fn write_file(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.write_all(bytes)
+    }
+
+    fn end_file(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        ZipWriter::finish(*self)?;
+        Ok(())
+    }
+}
+
+/// Packs a tar archive with GNU headers, whose numeric fields have no
+/// practical size limit, through an optional compressor.
+struct TarPacker {
+    builder: tar::Builder<Compressor>,
+    /// The size given in the current file's header.
+    size: u64,
+    /// How much of that size is still to be written.
+    remaining: u64,
+}
+
+impl TarPacker {
+    /// Writes the header of an entry, with any long-name entry it needs.
+    fn append(&mut self, mut header: tar::Header, name: &str, link: Option<&Path>) -> io::Result<()> {
+        match link {
+            Some(target) => self.builder.append_link(&mut header, name, target),
+            None => self.builder.append_data(&mut header, name, io::empty()),
+        }
+    }
+}
+
+impl Packer for TarPacker {
+    fn add_dir(&mut self, name: &str, metadata: &Metadata) -> io::Result<()> {
+        let header = tar_header(tar::EntryType::Directory, metadata, 0o755, 0);
+        self.append(header, &format!("{}/", name), None)
+    }
+
+    fn add_link(&mut self, name: &str, target: &Path, metadata: &Metadata) -> io::Result<()> {
+        let header = tar_header(tar::EntryType::Symlink, metadata, 0o777, 0);
+        self.append(header, name, Some(target))
+    }
+
+    fn start_file(&mut self, name: &str, metadata: &Metadata) -> io::Result<()> {
+        self.size = metadata.len;
+        self.remaining = metadata.len;
+        let header = tar_header(tar::EntryType::Regular, metadata, 0o644, metadata.len);
+        // The contents are written by the calls that follow, so the header
+        // goes out with none.
+        self.append(header, name, None)
+    }
+
+    fn write_file(&mut self, bytes: &[u8]) -> io::Result<()> {
+        // A file that grew since its size was read is cut to the size in its header.
+        let len = bytes.len().min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+        self.builder.get_mut().write_all(&bytes[..len])?;
+        self.remaining -= len as u64;
+        Ok(())
+    }
+
+    fn end_file(&mut self) -> io::Result<()> {
+        // A file that shrank is made up to its size with zeros, then every
+        // file is padded to a whole block.
+        let padding = self.remaining + (BLOCK_SIZE - self.size % BLOCK_SIZE) % BLOCK_SIZE;
+        io::copy(&mut io::repeat(0).take(padding), self.builder.get_mut())?;
+        self.remaining = 0;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        self.builder.into_inner()?.finish()
+    }
+}
+
+fn tar_header(entry_type: tar::EntryType, metadata: &Metadata, default_mode: u32, size: u64) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(entry_type);
+    header.set_mode(metadata.permissions.unwrap_or(default_mode));
+    header.set_size(size);
+    if let Some(modified) = metadata.modified {
+        header.set_mtime(u64::try_from(format::unix_seconds(modified)).unwrap_or(0));
+    }
+    header
+}
+
+/// The compression wrapped around a tar archive.
+enum Compressor {
+    Plain(Sink),
+    Gzip(GzEncoder<Sink>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, Sink>),
+}
+
+impl Compressor {
+    /// Writes out what the compressor still holds and its trailer.
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Compressor::Plain(_) => Ok(()),
+            Compressor::Gzip(encoder) => encoder.finish().map(drop),
+            #[cfg(feature = "zstd")]
+            Compressor::Zstd(encoder) => encoder.finish().map(drop),
+        }
+    }
+}
+
+impl Write for Compressor {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        match self {
+            Compressor::Plain(sink) => sink.write(bytes),
+            Compressor::Gzip(encoder) => encoder.write(bytes),
+            #[cfg(feature = "zstd")]
+            Compressor::Zstd(encoder) => encoder.write(bytes),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Compressor::Plain(sink) => sink.flush(),
+            Compressor::Gzip(encoder) => encoder.flush(),
+            #[cfg(feature = "zstd")]
+            Compressor::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+fn entry_options(metadata: &Metadata) -> FullFileOptions<'static> {
+    let mut options = FullFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .large_file(!metadata.is_dir() && metadata.len >= LARGE_FILE);
+    if let Some(mode) = metadata.permissions {
+        options = options.unix_permissions(mode);
+    }
+    if let Some(modified) = metadata.modified {
+        if let Some(time) = dos_time(modified) {
+            options = options.last_modified_time(time);
+        }
+        if let Ok(seconds) = u32::try_from(format::unix_seconds(modified)) {
+            // Flag bit 0: only the modification time follows.
+            let mut field = vec![1];
+            field.extend_from_slice(&seconds.to_le_bytes());
+            let _ = options.add_extra_data(EXTENDED_TIMESTAMP, field, false);
+        }
+    }
+    options
+}
+
+/// The DOS date and time zip headers carry, given in UTC. None outside the
+/// years 1980 to 2107 that the format can hold.
+fn dos_time(time: SystemTime) -> Option<DateTime> {
+    let (year, month, day, hour, minute, second) = format::utc_fields(time);
+    let narrow = |value: i64| u8::try_from(value).ok();
+    DateTime::from_date_and_time(
+        u16::try_from(year).ok()?,
+        month as u8,
+        day as u8,
+        narrow(hour)?,
+        narrow(minute)?,
+        narrow(second)?,
+    )
+    .ok()
+}
+
+/// The `Content-Disposition` value offering `name` as a download, with a
+/// plain ASCII fallback for clients that ignore the UTF-8 form.
+pub fn attachment(name: &str) -> String {
+    let ascii_name = name.chars().map(|c| if c.is_ascii_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.') { c } else { '_' });
+    // RFC 5987: the UTF-8 bytes, with everything but its attr-chars escaped.
+    let mut encoded = String::new();
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    format!("attachment; filename=\"{}\"; filename*=UTF-8''{}", ascii_name.collect::<String>(), encoded)
+}
+
+/// The name a download of the directory `tail` is offered under, before
+/// its extension: the directory's own name, or the served root's.
+pub fn archive_name(root: &Path, tail: &str) -> String {
+    match tail.trim_end_matches('/').rsplit('/').next().filter(|name| !name.is_empty()) {
+        Some(name) => name.to_string(),
+        None => root.file_name().map_or("download".to_string(), |name| name.to_string_lossy().into_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::links::{Confinement, SymlinkPolicy};
+    use crate::vfs::MemoryFs;
+    use crate::visibility::Visibility;
+    use std::io::Cursor;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_archive_stream_packs_the_tree_as_zip() {
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/docs/readme.txt", "hello");
+        fs.add_file("/srv/docs/sub/data.csv", "a,b\n".repeat(10_000));
+        fs.add_file("/srv/docs/.secret", "hidden");
+        fs.add_dir("/srv/docs/empty");
+        let confine = Confinement { root: PathBuf::from("/srv"), policy: SymlinkPolicy::Inside };
+        let rules = Visibility::default().rules(&fs, Path::new("/srv"), "docs");
+        let walk = Walk::new(&fs, Path::new("/srv/docs"), &confine, usize::MAX, rules);
+
+        let mut bytes = Vec::new();
+        ArchiveStream::new(fs, walk, ArchiveFormat::Zip).unwrap().read_to_end(&mut bytes).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut names = archive.file_names().map(str::to_string).collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["empty/", "readme.txt", "sub/", "sub/data.csv"]);
+        let mut data = String::new();
+        archive.by_name("sub/data.csv").unwrap().read_to_string(&mut data).unwrap();
+        assert_eq!(data, "a,b\n".repeat(10_000));
+    }
+
+    #[test]
+    fn test_archive_stream_packs_the_tree_as_tar() {
+        let long_name = format!("/srv/docs/{}/notes.txt", "nested".repeat(30));
+        let tree = || {
+            let fs = MemoryFs::new();
+            fs.add_file("/srv/docs/readme.txt", "hello");
+            fs.add_file(&long_name, "x".repeat(CHUNK_SIZE + 1));
+            fs
+        };
+        let confine = Confinement { root: PathBuf::from("/srv"), policy: SymlinkPolicy::Inside };
+
+        let formats = [
+            ArchiveFormat::Tar,
+            ArchiveFormat::TarGz,
+            #[cfg(feature = "zstd")]
+            ArchiveFormat::TarZst,
+        ];
+        for format in formats {
+            let fs = tree();
+            let rules = Visibility::default().rules(&fs, Path::new("/srv"), "docs");
+            let walk = Walk::new(&fs, Path::new("/srv/docs"), &confine, usize::MAX, rules);
+            let mut bytes = Vec::new();
+            ArchiveStream::new(fs, walk, format).unwrap().read_to_end(&mut bytes).unwrap();
+            let reader: Box<dyn Read> = match format {
+                ArchiveFormat::TarGz => Box::new(flate2::read::GzDecoder::new(Cursor::new(bytes))),
+                #[cfg(feature = "zstd")]
+                ArchiveFormat::TarZst => Box::new(zstd::Decoder::new(Cursor::new(bytes)).unwrap()),
+                _ => Box::new(Cursor::new(bytes)),
+            };
+            let mut archive = tar::Archive::new(reader);
+            let mut entries = Vec::new();
+            for entry in archive.entries().unwrap() {
+                let mut entry = entry.unwrap();
+                let mut contents = String::new();
+                entry.read_to_string(&mut contents).unwrap();
+                entries.push((entry.path().unwrap().to_string_lossy().into_owned(), entry.header().mode().unwrap(), contents.len()));
+            }
+            entries.sort();
+            let long_relative = long_name.trim_start_matches("/srv/docs/").to_string();
+            let long_dir = format!("{}/", Path::new(&long_relative).parent().unwrap().display());
+            assert_eq!(entries, [(long_dir, 0o755, 0), (long_relative, 0o644, CHUNK_SIZE + 1), ("readme.txt".to_string(), 0o644, 5)], "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn test_archive_format() {
+        assert_eq!(ArchiveFormat::parse("tgz"), Some(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::parse("rar"), None);
+        assert_eq!(ArchiveFormat::parse("tar").map(ArchiveFormat::extension), Some("tar"));
+    }
+
+    #[test]
+    fn test_dos_time() {
+        let time = format::from_civil(2026, 10, 18, 20, 58, 3);
+        let dos = dos_time(time).unwrap();
+        assert_eq!((dos.year(), dos.month(), dos.day(), dos.hour(), dos.minute()), (2026, 10, 18, 20, 58));
+        assert!(dos_time(format::from_civil(1970, 1, 1, 0, 0, 0)).is_none());
+    }
+
+    #[test]
+    fn test_archive_name() {
+        assert_eq!(archive_name(Path::new("/srv/files"), "docs/sub/"), "sub");
+        assert_eq!(archive_name(Path::new("/srv/files"), ""), "files");
+        assert_eq!(attachment("my \"docs\".zip"), "attachment; filename=\"my _docs_.zip\"; filename*=UTF-8''my%20%22docs%22.zip");
+        assert_eq!(archive_name(Path::new("/srv/files"), "photos/café/"), "café");
+        assert_eq!(attachment("café.zip"), "attachment; filename=\"caf_.zip\"; filename*=UTF-8''caf%C3%A9.zip");
+    }
+}
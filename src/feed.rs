@@ -0,0 +1,364 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::links::Confinement;
+use crate::search::{Limits, Walk};
+use crate::sort::{Sort, SortKey, SortOrder, natural_cmp};
+use crate::vfs::FileSystem;
+use crate::visibility::Rules;
+use crate::{format, get_mime_type, is_audio_file, is_image_file, is_video_file, url_encode};
+
+/// How many entries a feed holds unless `?limit=` asks for another number.
+pub const DEFAULT_ENTRIES: usize = 20;
+
+/// A file picked for a feed. Files order by modification time first.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FeedFile {
+    pub modified: SystemTime,
+    /// The path relative to the directory the feed is for.
+    pub relative: PathBuf,
+    pub size: u64,
+}
+
+/// Walks at most `limits.max_depth` levels below `start` and keeps the
+/// `count` most recently modified files that `wanted` accepts, newest first.
+/// Files without a modification time count as the oldest.
+pub fn recent_files(
+    fs: &dyn FileSystem,
+    start: &Path,
+    confine: &Confinement,
+    rules: Rules,
+    limits: Limits,
+    count: usize,
+    wanted: impl Fn(&Path) -> bool,
+) -> Vec<FeedFile> {
+    let deadline = Instant::now() + limits.timeout;
+    let mut walk = Walk::new(fs, start, confine, limits.max_depth, rules);
+    // A min-heap of the newest files seen so far, so memory stays bounded by `count`.
+    let mut newest = BinaryHeap::new();
+    while let Some(step) = walk.next_step(fs) {
+        if Instant::now() >= deadline {
+            break;
+        }
+        if !step.metadata.is_file() || !wanted(&step.path) {
+            continue;
+        }
+        newest.push(Reverse(FeedFile {
+            modified: step.metadata.modified.unwrap_or(UNIX_EPOCH),
+            relative: step.relative,
+            size: step.metadata.len,
+        }));
+        if newest.len() > count {
+            newest.pop();
+        }
+    }
+    newest.into_sorted_vec().into_iter().map(|Reverse(file)| file).collect()
+}
+
+/// Walks at most `limits.max_depth` levels below `start` and collects the
+/// files that `wanted` accepts, in the order they are found, until
+/// `limits.max_results` are found or the time runs out.
+pub fn matching_files(
+    fs: &dyn FileSystem,
+    start: &Path,
+    confine: &Confinement,
+    rules: Rules,
+    limits: Limits,
+    wanted: impl Fn(&Path) -> bool,
+) -> Vec<FeedFile> {
+    let deadline = Instant::now() + limits.timeout;
+    let mut walk = Walk::new(fs, start, confine, limits.max_depth, rules);
+    let mut files = Vec::new();
+    while files.len() < limits.max_results
+        && let Some(step) = walk.next_step(fs)
+    {
+        if Instant::now() >= deadline {
+            break;
+        }
+        if step.metadata.is_file() && wanted(&step.path) {
+            files.push(FeedFile {
+                modified: step.metadata.modified.unwrap_or(UNIX_EPOCH),
+                relative: step.relative,
+                size: step.metadata.len,
+            });
+        }
+    }
+    files
+}
+
+/// Where a feed lives, for building the absolute links inside it.
+pub struct Channel<'a> {
+    /// The scheme and authority the client reached the server at, e.g.
+    /// `http://files.example:8080`, or empty to keep links root-relative.
+    pub origin: &'a str,
+    /// The directory relative to the served root.
+    pub tail: &'a str,
+    /// The query string of the feed itself, starting with '?'.
+    pub self_query: String,
+}
+
+impl Channel<'_> {
+    fn title(&self) -> String {
+        format!("/{}", self.tail.trim_matches('/'))
+    }
+
+    fn dir_url(&self) -> String {
+        match self.tail.trim_matches('/') {
+            "" => format!("{}/", self.origin),
+            tail => format!("{}/{}/", self.origin, url_encode(tail)),
+        }
+    }
+
+    fn file_url(&self, file: &FeedFile) -> String {
+        format!("{}/{}", self.origin, url_encode(&Path::new(self.tail).join(&file.relative).display().to_string()))
+    }
+}
+
+/// Renders an Atom feed with one entry per file. Each entry links to the
+/// file's preview and carries the file itself as an enclosure; images also
+/// get a thumbnail reference and an inline `<img>`.
+pub fn atom(channel: &Channel, files: &[FeedFile]) -> String {
+    let updated = files.iter().map(|file| file.modified).max().unwrap_or_else(SystemTime::now);
+    let dir_url = xml_escape(&channel.dir_url());
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\" xmlns:media=\"http://search.yahoo.com/mrss/\">\n");
+    xml.push_str(&format!("  <id>{}</id>\n", dir_url));
+    xml.push_str(&format!("  <title>{}</title>\n", xml_escape(&channel.title())));
+    xml.push_str(&format!("  <updated>{}</updated>\n", format::format_rfc3339(updated)));
+    xml.push_str("  <author><name>Rust Web Explorer</name></author>\n");
+    xml.push_str("  <generator>Rust Web Explorer</generator>\n");
+    xml.push_str(&format!(
+        "  <link rel=\"self\" type=\"application/atom+xml\" href=\"{}{}\"/>\n",
+        dir_url,
+        xml_escape(&channel.self_query)
+    ));
+    xml.push_str(&format!("  <link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>\n", dir_url));
+    for file in files {
+        let url = xml_escape(&channel.file_url(file));
+        let mime_type = get_mime_type(&file.relative);
+        let title = xml_escape(&file.relative.display().to_string());
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <id>{}</id>\n", url));
+        xml.push_str(&format!("    <title>{}</title>\n", title));
+        xml.push_str(&format!("    <updated>{}</updated>\n", format::format_rfc3339(file.modified)));
+        xml.push_str(&format!("    <link rel=\"alternate\" type=\"text/html\" href=\"{}?view\"/>\n", url));
+        xml.push_str(&format!(
+            "    <link rel=\"enclosure\" type=\"{}\" length=\"{}\" href=\"{}\"/>\n",
+            mime_type, file.size, url
+        ));
+        xml.push_str(&format!("    <summary>{}, {}</summary>\n", mime_type, format::human_size(file.size)));
+        if is_image_file(&file.relative) {
+            xml.push_str(&format!("    <media:thumbnail url=\"{}\"/>\n", url));
+            let img = format!("<img src=\"{}\" alt=\"{}\">", url, title);
+            xml.push_str(&format!("    <content type=\"html\">{}</content>\n", xml_escape(&img)));
+        }
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+/// Whether a file belongs in the podcast feed.
+pub fn is_media_file(path: &Path) -> bool {
+    is_video_file(path) || is_audio_file(path)
+}
+
+/// Renders an RSS 2.0 feed with one item per file, each carrying the file as
+/// an `<enclosure>`, the way podcast apps expect episodes.
+pub fn rss(channel: &Channel, files: &[FeedFile]) -> String {
+    let updated = files.iter().map(|file| file.modified).max().unwrap_or_else(SystemTime::now);
+    let dir_url = xml_escape(&channel.dir_url());
+    let title = xml_escape(&channel.title());
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str("  <channel>\n");
+    xml.push_str(&format!("    <title>{}</title>\n", title));
+    xml.push_str(&format!("    <link>{}</link>\n", dir_url));
+    xml.push_str(&format!("    <description>Audio and video files in {}</description>\n", title));
+    xml.push_str(&format!(
+        "    <atom:link rel=\"self\" type=\"application/rss+xml\" href=\"{}{}\"/>\n",
+        dir_url,
+        xml_escape(&channel.self_query)
+    ));
+    xml.push_str(&format!("    <lastBuildDate>{}</lastBuildDate>\n", format::format_http_date(updated)));
+    xml.push_str("    <generator>Rust Web Explorer</generator>\n");
+    for file in files {
+        let url = xml_escape(&channel.file_url(file));
+        xml.push_str("    <item>\n");
+        xml.push_str(&format!("      <title>{}</title>\n", xml_escape(&file.relative.display().to_string())));
+        xml.push_str(&format!("      <link>{}?view</link>\n", url));
+        xml.push_str(&format!("      <guid isPermaLink=\"true\">{}</guid>\n", url));
+        xml.push_str(&format!("      <pubDate>{}</pubDate>\n", format::format_http_date(file.modified)));
+        xml.push_str(&format!(
+            "      <enclosure url=\"{}\" length=\"{}\" type=\"{}\"/>\n",
+            url,
+            file.size,
+            get_mime_type(&file.relative)
+        ));
+        xml.push_str("    </item>\n");
+    }
+    xml.push_str("  </channel>\n</rss>\n");
+    xml
+}
+
+/// Orders files for a playlist. Names compare by their whole relative path,
+/// so the files of each subdirectory stay together.
+pub fn sort_files(files: &mut [FeedFile], sort: Sort) {
+    files.sort_by(|a, b| {
+        let ordering = match sort.key {
+            SortKey::Name | SortKey::Unsorted => Ordering::Equal,
+            SortKey::Size => a.size.cmp(&b.size),
+            SortKey::Mtime => a.modified.cmp(&b.modified),
+            SortKey::Type => get_mime_type(&a.relative).cmp(get_mime_type(&b.relative)),
+        }
+        .then_with(|| natural_cmp(&a.relative.to_string_lossy(), &b.relative.to_string_lossy()));
+        match sort.order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        }
+    });
+}
+
+/// Renders an extended M3U playlist (UTF-8, as `.m3u8` files are) that
+/// players such as VLC or mpv can stream the files from.
+pub fn m3u(channel: &Channel, files: &[FeedFile]) -> String {
+    // A line break inside a name would end the directive early.
+    let one_line = |text: String| text.replace(|c: char| c.is_control(), " ");
+    let mut playlist = String::from("#EXTM3U\n");
+    playlist.push_str(&format!("#PLAYLIST:{}\n", one_line(channel.title())));
+    for file in files {
+        playlist.push_str(&format!("#EXTINF:-1,{}\n", one_line(file.relative.display().to_string())));
+        playlist.push_str(&channel.file_url(file));
+        playlist.push('\n');
+    }
+    playlist
+}
+
+/// Escapes text for use in XML content and attribute values.
+pub fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::links::SymlinkPolicy;
+    use crate::vfs::MemoryFs;
+    use crate::visibility::Visibility;
+    use std::time::Duration;
+
+    const LIMITS: Limits = Limits { max_depth: 16, max_results: 100, timeout: Duration::from_secs(60) };
+
+    fn file(relative: &str, seconds: u64, size: u64) -> FeedFile {
+        FeedFile { modified: UNIX_EPOCH + Duration::from_secs(seconds), relative: PathBuf::from(relative), size }
+    }
+
+    fn confine() -> Confinement {
+        Confinement { root: PathBuf::from("/srv"), policy: SymlinkPolicy::Inside }
+    }
+
+    fn rules(fs: &dyn FileSystem) -> Rules {
+        Visibility::default().rules(fs, Path::new("/srv"), "")
+    }
+
+    #[test]
+    fn test_recent_files_keeps_newest() {
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/a.txt", "a");
+        fs.add_file("/srv/sub/b.txt", "bb");
+        fs.add_file("/srv/sub/c.log", "ccc");
+        let all = recent_files(&fs, Path::new("/srv"), &confine(), rules(&fs), LIMITS, 10, |_| true);
+        assert_eq!(all.len(), 3);
+        assert!(all.windows(2).all(|pair| pair[0].modified >= pair[1].modified));
+
+        let one = recent_files(&fs, Path::new("/srv"), &confine(), rules(&fs), LIMITS, 1, |_| true);
+        assert_eq!(one, all[..1]);
+
+        let shallow = recent_files(&fs, Path::new("/srv"), &confine(), rules(&fs), Limits { max_depth: 0, ..LIMITS }, 10, |_| true);
+        assert_eq!(shallow.len(), 1);
+        assert_eq!(shallow[0].relative, PathBuf::from("a.txt"));
+
+        let logs = recent_files(&fs, Path::new("/srv"), &confine(), rules(&fs), LIMITS, 10, |path| path.ends_with("c.log"));
+        assert_eq!(logs.len(), 1);
+    }
+
+    #[test]
+    fn test_atom() {
+        let channel = Channel { origin: "http://files.example", tail: "drop box", self_query: "?format=atom".to_string() };
+        let files = [file("new photo.jpg", 2_000_000_000, 2048), file("sub/notes & todo.txt", 1_000_000_000, 12)];
+        let xml = atom(&channel, &files);
+        assert!(xml.contains("<id>http://files.example/drop%20box/</id>"));
+        assert!(xml.contains("<title>/drop box</title>"));
+        assert!(xml.contains("<updated>2033-05-18T03:33:20Z</updated>"));
+        assert!(xml.contains("href=\"http://files.example/drop%20box/?format=atom\""));
+        assert!(xml.contains(
+            "<link rel=\"enclosure\" type=\"image/jpeg\" length=\"2048\" href=\"http://files.example/drop%20box%2Fnew%20photo.jpg\"/>"
+        ));
+        assert!(xml.contains("<media:thumbnail url=\"http://files.example/drop%20box%2Fnew%20photo.jpg\"/>"));
+        assert!(xml.contains("&lt;img src=&quot;http://files.example/drop%20box%2Fnew%20photo.jpg&quot;"));
+        assert!(xml.contains("<title>sub/notes &amp; todo.txt</title>"));
+        assert_eq!(xml.matches("<media:thumbnail").count(), 1);
+    }
+
+    #[test]
+    fn test_rss() {
+        let channel = Channel { origin: "http://files.example", tail: "meetings", self_query: "?format=rss".to_string() };
+        let files = [file("standup.mp4", 951_827_696, 4096), file("retro.mp3", 0, 10)];
+        let xml = rss(&channel, &files);
+        assert!(xml.contains("<link>http://files.example/meetings/</link>"));
+        assert!(xml.contains("<lastBuildDate>Tue, 29 Feb 2000 12:34:56 GMT</lastBuildDate>"));
+        assert!(xml.contains(
+            "<enclosure url=\"http://files.example/meetings%2Fstandup.mp4\" length=\"4096\" type=\"video/mp4\"/>"
+        ));
+        assert!(xml.contains("type=\"audio/mpeg\""));
+        assert!(xml.contains("<pubDate>Thu, 01 Jan 1970 00:00:00 GMT</pubDate>"));
+        assert_eq!(xml.matches("<item>").count(), 2);
+    }
+
+    #[test]
+    fn test_is_media_file() {
+        assert!(is_media_file(Path::new("standup.MP4")));
+        assert!(is_media_file(Path::new("retro.mp3")));
+        assert!(!is_media_file(Path::new("notes.txt")));
+    }
+
+    #[test]
+    fn test_sort_files() {
+        let mut files = [file("s01/e10.mkv", 3, 1), file("s01/e2.mkv", 1, 3), file("extra.mp4", 2, 2)];
+        let names = |files: &[FeedFile]| files.iter().map(|file| file.relative.display().to_string()).collect::<Vec<_>>();
+        sort_files(&mut files, Sort::default());
+        assert_eq!(names(&files), ["extra.mp4", "s01/e2.mkv", "s01/e10.mkv"]);
+        sort_files(&mut files, Sort { key: SortKey::Mtime, order: SortOrder::Desc, ..Sort::default() });
+        assert_eq!(names(&files), ["s01/e10.mkv", "extra.mp4", "s01/e2.mkv"]);
+    }
+
+    #[test]
+    fn test_m3u() {
+        let channel = Channel { origin: "http://files.example", tail: "shows/season 1", self_query: String::new() };
+        let playlist = m3u(&channel, &[file("e01.mkv", 0, 1), file("extras/odd\nname.mp3", 0, 1)]);
+        assert_eq!(
+            playlist,
+            "#EXTM3U\n#PLAYLIST:/shows/season 1\n\
+             #EXTINF:-1,e01.mkv\nhttp://files.example/shows%2Fseason%201%2Fe01.mkv\n\
+             #EXTINF:-1,extras/odd name.mp3\nhttp://files.example/shows%2Fseason%201%2Fextras%2Fodd%0Aname.mp3\n"
+        );
+    }
+
+    #[test]
+    fn test_xml_escape() {
+        assert_eq!(xml_escape("a < b & \"c\" > 'd'"), "a &lt; b &amp; &quot;c&quot; &gt; &apos;d&apos;");
+    }
+}
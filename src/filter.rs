@@ -0,0 +1,102 @@
+use globset::{GlobBuilder, GlobMatcher};
+
+use crate::http::Query;
+use crate::url_encode;
+
+/// Narrows a listing to the names matching `?filter=` (a glob such as
+/// `*.log`) and containing `?q=`. Both compare without regard to case.
+#[derive(Debug, Default)]
+pub struct NameFilter {
+    pub pattern: Option<String>,
+    pub q: Option<String>,
+    glob: Option<GlobMatcher>,
+    substring: Option<String>,
+}
+
+impl NameFilter {
+    pub fn from_query(query: &Query) -> Result<NameFilter, globset::Error> {
+        let pattern = query.get("filter").filter(|pattern| !pattern.is_empty()).map(str::to_string);
+        let glob = match &pattern {
+            Some(pattern) => Some(GlobBuilder::new(pattern).case_insensitive(true).build()?.compile_matcher()),
+            None => None,
+        };
+        let q = query.get("q").filter(|q| !q.is_empty()).map(str::to_string);
+        let substring = q.as_deref().map(str::to_lowercase);
+        Ok(NameFilter { pattern, q, glob, substring })
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.glob.is_some() || self.substring.is_some()
+    }
+
+    /// The filter's own parameters, each prefixed with '&', for links that
+    /// change something else about the listing but should keep it filtered.
+    pub fn to_query_suffix(&self) -> String {
+        let mut suffix = String::new();
+        if let Some(pattern) = &self.pattern {
+            suffix.push_str(&format!("&filter={}", url_encode(pattern)));
+        }
+        if let Some(q) = &self.q {
+            suffix.push_str(&format!("&q={}", url_encode(q)));
+        }
+        suffix
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.glob.as_ref().is_none_or(|glob| glob.is_match(name))
+            && self.substring.as_ref().is_none_or(|substring| name.to_lowercase().contains(substring.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(raw_query: &str) -> NameFilter {
+        NameFilter::from_query(&Query::parse(raw_query)).unwrap()
+    }
+
+    #[test]
+    fn test_glob() {
+        let logs = filter("filter=*.log");
+        assert!(logs.is_active());
+        assert!(logs.matches("build.log"));
+        assert!(logs.matches("BUILD.LOG"));
+        assert!(!logs.matches("build.log.gz"));
+        assert!(filter("filter=file?.txt").matches("file2.txt"));
+        assert!(filter("filter=%5Ba-c%5D*").matches("beta"));
+    }
+
+    #[test]
+    fn test_substring() {
+        let report = filter("q=Report");
+        assert!(report.matches("monthly-report.pdf"));
+        assert!(!report.matches("summary.pdf"));
+    }
+
+    #[test]
+    fn test_glob_and_substring_combine() {
+        let both = filter("filter=*.log&q=error");
+        assert!(both.matches("error.log"));
+        assert!(!both.matches("access.log"));
+        assert!(!both.matches("error.txt"));
+    }
+
+    #[test]
+    fn test_to_query_suffix() {
+        assert_eq!(filter("").to_query_suffix(), "");
+        assert_eq!(filter("filter=*.log&q=a%26b").to_query_suffix(), "&filter=%2A.log&q=a%26b");
+    }
+
+    #[test]
+    fn test_empty_parameters_match_everything() {
+        let none = filter("filter=&q=");
+        assert!(!none.is_active());
+        assert!(none.matches("anything"));
+    }
+
+    #[test]
+    fn test_invalid_glob() {
+        assert!(NameFilter::from_query(&Query::parse("filter=%5Bunclosed")).is_err());
+    }
+}
@@ -0,0 +1,191 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Formats a byte count with binary units, e.g. `1.5 KiB`.
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Seconds since the Unix epoch, negative for earlier times.
+pub fn unix_seconds(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => after.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    }
+}
+
+/// Builds a time from a UTC calendar date, as stored in zip headers.
+pub fn from_civil(year: i64, month: u32, day: u32, hour: u32, minute: u32, second: u32) -> SystemTime {
+    let seconds = days_from_civil(year, month, day) * 86_400 + (hour * 3600 + minute * 60 + second) as i64;
+    if seconds >= 0 {
+        UNIX_EPOCH + Duration::from_secs(seconds as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(seconds.unsigned_abs())
+    }
+}
+
+/// Formats a time as `YYYY-MM-DD HH:MM:SS UTC`.
+pub fn format_timestamp(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second) = utc_fields(time);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC", year, month, day, hour, minute, second)
+}
+
+/// Formats a time as RFC 3339 in UTC, e.g. `2026-10-18T20:58:03Z`.
+pub fn format_rfc3339(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second) = utc_fields(time);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, hour, minute, second)
+}
+
+/// Formats a time as HTTP and RSS expect it, e.g. `Sun, 18 Oct 2026 20:58:03 GMT`.
+pub fn format_http_date(time: SystemTime) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let (year, month, day, hour, minute, second) = utc_fields(time);
+    // 1970-01-01 was a Thursday.
+    let weekday = WEEKDAYS[unix_seconds(time).div_euclid(86_400).rem_euclid(7) as usize];
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        weekday,
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        hour,
+        minute,
+        second
+    )
+}
+
+/// The UTC calendar date and time of day of `time`: year, month, day,
+/// hour, minute and second.
+pub fn utc_fields(time: SystemTime) -> (i64, u32, u32, i64, i64, i64) {
+    let seconds = unix_seconds(time);
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    let second_of_day = seconds.rem_euclid(86_400);
+    (year, month, day, second_of_day / 3600, second_of_day % 3600 / 60, second_of_day % 60)
+}
+
+/// Describes how long before `now` the time `then` was, e.g. `5 minutes ago`.
+pub fn relative_time(then: SystemTime, now: SystemTime) -> String {
+    let seconds = match now.duration_since(then) {
+        Ok(elapsed) => elapsed.as_secs(),
+        // Clocks disagree or the file is dated in the future.
+        Err(_) => return "in the future".to_string(),
+    };
+    let (amount, unit) = match seconds {
+        0..=59 => return "just now".to_string(),
+        60..=3_599 => (seconds / 60, "minute"),
+        3_600..=86_399 => (seconds / 3_600, "hour"),
+        86_400..=2_591_999 => (seconds / 86_400, "day"),
+        2_592_000..=31_535_999 => (seconds / 2_592_000, "month"),
+        _ => (seconds / 31_536_000, "year"),
+    };
+    format!("{} {}{} ago", amount, unit, if amount == 1 { "" } else { "s" })
+}
+
+/// Renders Unix permission bits the way `ls -l` does, e.g. `drwxr-xr-x`.
+pub fn permissions(mode: u32, is_dir: bool) -> String {
+    let mut rendered = String::with_capacity(10);
+    rendered.push(if is_dir { 'd' } else { '-' });
+    for shift in [6, 3, 0] {
+        let bits = mode >> shift;
+        rendered.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        rendered.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        rendered.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    rendered
+}
+
+// Calendar conversions from Howard Hinnant's "chrono-Compatible Low-Level
+// Date Algorithms", valid for the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!(human_size(0), "0 B");
+        assert_eq!(human_size(1023), "1023 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(5 * 1024 * 1024), "5.0 MiB");
+        assert_eq!(human_size(u64::MAX), "16.0 EiB");
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(UNIX_EPOCH + Duration::from_secs(951_827_696)), "2000-02-29 12:34:56 UTC");
+        assert_eq!(format_timestamp(UNIX_EPOCH - Duration::from_secs(1)), "1969-12-31 23:59:59 UTC");
+    }
+
+    #[test]
+    fn test_format_rfc3339() {
+        assert_eq!(format_rfc3339(UNIX_EPOCH + Duration::from_secs(951_827_696)), "2000-02-29T12:34:56Z");
+    }
+
+    #[test]
+    fn test_format_http_date() {
+        assert_eq!(format_http_date(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(format_http_date(UNIX_EPOCH + Duration::from_secs(951_827_696)), "Tue, 29 Feb 2000 12:34:56 GMT");
+        assert_eq!(format_http_date(UNIX_EPOCH - Duration::from_secs(1)), "Wed, 31 Dec 1969 23:59:59 GMT");
+    }
+
+    #[test]
+    fn test_relative_time() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        let ago = |seconds| relative_time(now - Duration::from_secs(seconds), now);
+        assert_eq!(ago(5), "just now");
+        assert_eq!(ago(60), "1 minute ago");
+        assert_eq!(ago(7_200), "2 hours ago");
+        assert_eq!(ago(86_400 * 3), "3 days ago");
+        assert_eq!(ago(86_400 * 65), "2 months ago");
+        assert_eq!(ago(86_400 * 800), "2 years ago");
+        assert_eq!(relative_time(now + Duration::from_secs(60), now), "in the future");
+    }
+
+    #[test]
+    fn test_permissions() {
+        assert_eq!(permissions(0o755, true), "drwxr-xr-x");
+        assert_eq!(permissions(0o100644, false), "-rw-r--r--");
+        assert_eq!(permissions(0o600, false), "-rw-------");
+    }
+
+    #[test]
+    fn test_from_civil_roundtrip() {
+        let time = from_civil(2026, 10, 18, 20, 58, 3);
+        assert_eq!(format_timestamp(time), "2026-10-18 20:58:03 UTC");
+        assert_eq!(unix_seconds(from_civil(1970, 1, 1, 0, 0, 0)), 0);
+        assert_eq!(unix_seconds(from_civil(1969, 12, 31, 23, 59, 59)), -1);
+    }
+}
@@ -0,0 +1,323 @@
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+use minijinja::Value;
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+
+use crate::search::{Limits, Stopped, Walk};
+use crate::templates::{Templates, url_value};
+use crate::url_encode;
+use crate::vfs::FileSystem;
+
+/// Files larger than this are skipped rather than searched.
+pub const FILE_LIMIT: u64 = 8 * 1024 * 1024;
+
+/// Matched lines longer than this many characters are cut down to the part
+/// around the first match.
+const LINE_LIMIT: usize = 400;
+
+/// How much of a file is checked for NUL bytes to tell binary files apart.
+const BINARY_SNIFF_LEN: usize = 8192;
+
+/// Compiles what was typed into the search box, escaping it unless it is
+/// meant as a regular expression.
+pub fn build_pattern(pattern: &str, regex: bool, ignore_case: bool) -> Result<Regex, regex::Error> {
+    let source = if regex { pattern.to_string() } else { regex::escape(pattern) };
+    RegexBuilder::new(&source).case_insensitive(ignore_case).build()
+}
+
+/// A run of a matched line, highlighted when it is part of a match.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Part {
+    pub text: String,
+    pub matched: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LineMatch {
+    /// Counted from 1, as in the preview's `#L<n>` anchors.
+    pub line: usize,
+    pub parts: Vec<Part>,
+}
+
+/// The matches in one file, rendered by the `file_matches` macro of `grep_results.html`.
+#[derive(Debug, Serialize)]
+pub struct FileMatches {
+    /// The path relative to the directory being searched.
+    pub path: String,
+    pub url: Value,
+    pub matches: Vec<LineMatch>,
+}
+
+/// Totals for the `summary` macro of `grep_results.html`, rendered once the walk ends.
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+    pub files_searched: usize,
+    pub files_matched: usize,
+    /// Binary, oversized or unreadable files.
+    pub files_skipped: usize,
+    pub matches: usize,
+    pub stopped: Option<Stopped>,
+    pub depth_limited: bool,
+    pub max_results: usize,
+    pub timeout_secs: u64,
+}
+
+/// Splits `line` into plain and highlighted parts, keeping only a window
+/// around the first match when the line is very long.
+pub fn highlight(line: &str, regex: &Regex) -> Vec<Part> {
+    let (start, end) = match line.char_indices().nth(LINE_LIMIT) {
+        None => (0, line.len()),
+        Some(_) => {
+            let first = regex.find(line).map_or(0, |found| found.start());
+            let start = line[..first].char_indices().rev().nth(LINE_LIMIT / 4).map_or(0, |(index, _)| index);
+            let end = line[start..].char_indices().nth(LINE_LIMIT).map_or(line.len(), |(index, _)| start + index);
+            (start, end)
+        }
+    };
+    let window = &line[start..end];
+
+    let mut parts = Vec::new();
+    let mut push = |text: &str, matched: bool| {
+        if !text.is_empty() {
+            parts.push(Part { text: text.to_string(), matched });
+        }
+    };
+    if start > 0 {
+        push("…", false);
+    }
+    let mut last = 0;
+    for found in regex.find_iter(window) {
+        push(&window[last..found.start()], false);
+        push(found.as_str(), true);
+        last = found.end();
+    }
+    push(&window[last..], false);
+    if end < line.len() {
+        push("…", false);
+    }
+    parts
+}
+
+/// Reads a file for searching, or returns None when it is too large, cannot
+/// be read, or does not look like UTF-8 text.
+pub fn read_text(fs: &dyn FileSystem, path: &Path, len: u64) -> Option<String> {
+    if len > FILE_LIMIT {
+        return None;
+    }
+    let mut contents = Vec::new();
+    fs.open(path).and_then(|file| file.take(FILE_LIMIT).read_to_end(&mut contents)).ok()?;
+    if contents[..contents.len().min(BINARY_SNIFF_LEN)].contains(&0) {
+        return None;
+    }
+    String::from_utf8(contents).ok()
+}
+
+enum Scan {
+    Matches(Vec<LineMatch>),
+    Skipped,
+}
+
+/// A response body that walks the tree and renders each file's matches as
+/// soon as it has been searched, so results show up while the walk goes on.
+pub struct GrepStream<F> {
+    fs: F,
+    walk: Walk,
+    regex: Regex,
+    templates: Arc<Templates>,
+    /// The searched directory relative to the served root, for building URLs.
+    tail: String,
+    deadline: Instant,
+    summary: Summary,
+    suffix: Option<String>,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl<F: FileSystem> GrepStream<F> {
+    /// Searches the files `walk` reaches. The body begins with `prefix` and
+    /// ends with the rendered summary followed by `suffix`.
+    pub fn new(
+        fs: F,
+        walk: Walk,
+        regex: Regex,
+        limits: Limits,
+        templates: Arc<Templates>,
+        tail: &str,
+        (prefix, suffix): (String, String),
+    ) -> GrepStream<F> {
+        GrepStream {
+            fs,
+            walk,
+            regex,
+            templates,
+            tail: tail.to_string(),
+            deadline: Instant::now() + limits.timeout,
+            summary: Summary { max_results: limits.max_results, timeout_secs: limits.timeout.as_secs(), ..Summary::default() },
+            suffix: Some(suffix),
+            buffer: prefix.into_bytes(),
+            position: 0,
+        }
+    }
+
+    /// Refills the buffer with the next file's matches, or with the closing
+    /// summary once the walk is over. Returns false when nothing is left.
+    fn fill(&mut self) -> io::Result<bool> {
+        self.buffer.clear();
+        self.position = 0;
+        while self.summary.stopped.is_none() {
+            if Instant::now() >= self.deadline {
+                self.summary.stopped = Some(Stopped::TimeLimit);
+                break;
+            }
+            let Some(step) = self.walk.next_step(&self.fs) else { break };
+            if !step.metadata.is_file() {
+                continue;
+            }
+            self.summary.files_searched += 1;
+            let matches = match self.scan(&step.path, step.metadata.len) {
+                Scan::Matches(matches) if matches.is_empty() => continue,
+                Scan::Matches(matches) => matches,
+                Scan::Skipped => {
+                    self.summary.files_skipped += 1;
+                    continue;
+                }
+            };
+            self.summary.files_matched += 1;
+            let file = FileMatches {
+                path: step.relative.display().to_string(),
+                url: url_value(format!("/{}", url_encode(&Path::new(&self.tail).join(&step.relative).display().to_string()))),
+                matches,
+            };
+            let html = self.templates.call_macro("grep_results.html", "file_matches", Value::from_serialize(&file));
+            self.buffer = html.map_err(io::Error::other)?.into_bytes();
+            return Ok(true);
+        }
+
+        let Some(suffix) = self.suffix.take() else { return Ok(false) };
+        self.summary.depth_limited = self.walk.depth_limited;
+        let summary = self.templates.call_macro("grep_results.html", "summary", Value::from_serialize(&self.summary));
+        self.buffer = summary.map_err(io::Error::other)?.into_bytes();
+        self.buffer.extend_from_slice(suffix.as_bytes());
+        Ok(true)
+    }
+
+    fn scan(&mut self, path: &Path, len: u64) -> Scan {
+        let Some(text) = read_text(&self.fs, path, len) else { return Scan::Skipped };
+
+        let mut matches = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            if Instant::now() >= self.deadline {
+                self.summary.stopped = Some(Stopped::TimeLimit);
+                break;
+            }
+            if self.regex.is_match(line) {
+                if self.summary.matches == self.summary.max_results {
+                    self.summary.stopped = Some(Stopped::ResultLimit);
+                    break;
+                }
+                self.summary.matches += 1;
+                matches.push(LineMatch { line: index + 1, parts: highlight(line, &self.regex) });
+            }
+        }
+        Scan::Matches(matches)
+    }
+}
+
+impl<F: FileSystem> Read for GrepStream<F> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.buffer.len() {
+            if !self.fill()? {
+                return Ok(0);
+            }
+        }
+        let len = out.len().min(self.buffer.len() - self.position);
+        out[..len].copy_from_slice(&self.buffer[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::links::{Confinement, SymlinkPolicy};
+    use crate::vfs::MemoryFs;
+    use crate::visibility::Visibility;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    const LIMITS: Limits = Limits { max_depth: 16, max_results: 100, timeout: Duration::from_secs(60) };
+
+    fn tree() -> MemoryFs {
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/logs/app.log", "starting\nERROR: disk full\nretrying\nerror again\n");
+        fs.add_file("/srv/logs/core.bin", b"error\0\x01\x02".to_vec());
+        fs.add_file("/srv/readme.txt", "nothing to see\n");
+        fs
+    }
+
+    fn grep(fs: MemoryFs, pattern: Regex, limits: Limits) -> String {
+        let templates = Arc::new(Templates::new(None).unwrap());
+        let rules = Visibility::default().rules(&fs, Path::new("/srv"), "");
+        let confine = Confinement { root: PathBuf::from("/srv"), policy: SymlinkPolicy::Inside };
+        let walk = Walk::new(&fs, Path::new("/srv"), &confine, limits.max_depth, rules);
+        let page = ("<prefix>".to_string(), "<suffix>".to_string());
+        let mut stream = GrepStream::new(fs, walk, pattern, limits, templates, "", page);
+        let mut html = String::new();
+        stream.read_to_string(&mut html).unwrap();
+        html
+    }
+
+    #[test]
+    fn test_build_pattern() {
+        assert!(build_pattern("a.c", false, false).unwrap().is_match("a.c"));
+        assert!(!build_pattern("a.c", false, false).unwrap().is_match("abc"));
+        assert!(build_pattern("a.c", true, false).unwrap().is_match("abc"));
+        assert!(build_pattern("ABC", false, true).unwrap().is_match("abc"));
+        assert!(build_pattern("(", true, false).is_err());
+    }
+
+    #[test]
+    fn test_highlight() {
+        let regex = build_pattern("o", false, false).unwrap();
+        let parts = highlight("foo bar", &regex);
+        let rendered = parts.iter().map(|part| if part.matched { format!("[{}]", part.text) } else { part.text.clone() });
+        assert_eq!(rendered.collect::<String>(), "f[o][o] bar");
+    }
+
+    #[test]
+    fn test_highlight_long_line_keeps_window_around_match() {
+        let line = format!("{}needle{}", "x".repeat(1000), "y".repeat(1000));
+        let parts = highlight(&line, &build_pattern("needle", false, false).unwrap());
+        assert_eq!(parts.first().unwrap().text, "…");
+        assert_eq!(parts.last().unwrap().text, "…");
+        assert!(parts.contains(&Part { text: "needle".to_string(), matched: true }));
+        assert!(parts.iter().map(|part| part.text.chars().count()).sum::<usize>() <= LINE_LIMIT + 2);
+    }
+
+    #[test]
+    fn test_stream_renders_matches_and_summary() {
+        let html = grep(tree(), build_pattern("error", false, true).unwrap(), LIMITS);
+        assert!(html.starts_with("<prefix>"));
+        assert!(html.ends_with("<suffix>"));
+        assert!(html.contains("<a href=\"/logs%2Fapp.log?view#L2\">2</a>"));
+        assert!(html.contains("<mark>ERROR</mark>: disk full"));
+        assert!(html.contains("<a href=\"/logs%2Fapp.log?view#L4\">4</a>"));
+        assert!(!html.contains("core.bin"));
+        assert!(!html.contains("readme.txt"));
+        assert!(html.contains("2 matches in 1 of 3 files"));
+        assert!(html.contains("1 binary, oversized or unreadable file skipped"));
+    }
+
+    #[test]
+    fn test_stream_stops_at_result_limit() {
+        let html = grep(tree(), build_pattern("error", false, true).unwrap(), Limits { max_results: 1, ..LIMITS });
+        assert!(html.contains("#L2\""));
+        assert!(!html.contains("#L4\""));
+        assert!(html.contains("stopped after the first 1 match"));
+    }
+}
@@ -0,0 +1,230 @@
+use std::io::{self, Read, Write};
+
+use crate::url_decode;
+
+/// The decoded `key=value` pairs of a query string, in request order.
+#[derive(Debug, Default)]
+pub struct Query {
+    pairs: Vec<(String, String)>,
+}
+
+impl Query {
+    pub fn parse(raw: &str) -> Query {
+        let pairs = raw
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (url_decode(&key.replace('+', " ")), url_decode(&value.replace('+', " ")))
+            })
+            .collect();
+        Query { pairs }
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.pairs.iter().any(|(k, _)| k == key)
+    }
+
+    /// The value of the first pair named `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    /// The values of every pair named `key`, in order.
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.pairs.iter().filter(move |(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+}
+
+/// The part of a body a `Range` header asks for, resolved against the
+/// body's length.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ByteRange {
+    /// No usable range was asked for, so the whole body is sent. Multiple
+    /// ranges and units other than bytes end up here too.
+    Whole,
+    /// The bytes from `start` to `end`, both included.
+    Partial { start: u64, end: u64 },
+    /// The range lies entirely past the end of the body.
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    pub fn parse(header: Option<&str>, len: u64) -> ByteRange {
+        let Some(spec) = header.and_then(|header| header.trim().strip_prefix("bytes=")) else { return ByteRange::Whole };
+        if spec.contains(',') {
+            return ByteRange::Whole;
+        }
+        let Some((first, last)) = spec.trim().split_once('-') else { return ByteRange::Whole };
+        let (first, last) = (first.trim(), last.trim());
+        if first.is_empty() {
+            // A suffix: the last N bytes.
+            return match last.parse::<u64>() {
+                Ok(0) => ByteRange::Unsatisfiable,
+                Ok(_) if len == 0 => ByteRange::Unsatisfiable,
+                Ok(suffix) => ByteRange::Partial { start: len - suffix.min(len), end: len - 1 },
+                Err(_) => ByteRange::Whole,
+            };
+        }
+        let Ok(start) = first.parse::<u64>() else { return ByteRange::Whole };
+        let end = match last {
+            "" => u64::MAX,
+            last => match last.parse::<u64>() {
+                Ok(end) if end >= start => end,
+                _ => return ByteRange::Whole,
+            },
+        };
+        if start >= len { ByteRange::Unsatisfiable } else { ByteRange::Partial { start, end: end.min(len - 1) } }
+    }
+}
+
+enum Body {
+    Bytes(Vec<u8>),
+    Stream { reader: Box<dyn Read + Send>, len: Option<u64> },
+}
+
+/// An HTTP response whose body is either already in memory or read lazily
+/// while it is written out, so large files never have to be buffered whole.
+pub struct Response {
+    status: &'static str,
+    headers: Vec<(String, String)>,
+    body: Body,
+}
+
+impl Response {
+    pub fn new(status: &'static str) -> Self {
+        Response { status, headers: Vec::new(), body: Body::Bytes(Vec::new()) }
+    }
+
+    pub fn ok() -> Self {
+        Self::new("200 OK")
+    }
+
+    pub fn header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = Body::Bytes(body.into());
+        self
+    }
+
+    /// Uses `reader` as the body. When `len` is known it is sent as the
+    /// Content-Length, otherwise the body is sent with chunked transfer
+    /// encoding, one chunk per read, so the client sees each part as soon
+    /// as it is produced.
+    pub fn stream(mut self, reader: Box<dyn Read + Send>, len: Option<u64>) -> Self {
+        self.body = Body::Stream { reader, len };
+        self
+    }
+
+    pub fn write_to(self, out: &mut dyn Write) -> io::Result<()> {
+        let mut head = format!("HTTP/1.1 {}\r\n", self.status);
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        let len = match &self.body {
+            Body::Bytes(bytes) => Some(bytes.len() as u64),
+            Body::Stream { len, .. } => *len,
+        };
+        match len {
+            Some(len) => head.push_str(&format!("Content-Length: {}\r\n", len)),
+            None => head.push_str("Transfer-Encoding: chunked\r\n"),
+        }
+        head.push_str("\r\n");
+        out.write_all(head.as_bytes())?;
+
+        match self.body {
+            Body::Bytes(bytes) => out.write_all(&bytes)?,
+            Body::Stream { mut reader, len: Some(_) } => {
+                io::copy(&mut reader, out)?;
+            }
+            Body::Stream { mut reader, len: None } => write_chunked(&mut reader, out)?,
+        }
+        out.flush()
+    }
+
+    #[cfg(test)]
+    pub fn into_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.write_to(&mut bytes).expect("writing to a Vec cannot fail");
+        bytes
+    }
+}
+
+/// Copies `reader` to `out` as HTTP/1.1 chunks, flushing after each one.
+fn write_chunked(reader: &mut dyn Read, out: &mut dyn Write) -> io::Result<()> {
+    let mut buffer = [0; 8192];
+    loop {
+        let len = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        out.write_all(format!("{:x}\r\n", len).as_bytes())?;
+        out.write_all(&buffer[..len])?;
+        out.write_all(b"\r\n")?;
+        out.flush()?;
+    }
+    out.write_all(b"0\r\n\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_query_parse() {
+        let query = Query::parse("view&sort=name&a+b%26c=1");
+        assert!(query.contains("view"));
+        assert!(query.contains("sort"));
+        assert!(query.contains("a b&c"));
+        assert!(!query.contains("missing"));
+        assert!(!Query::parse("").contains(""));
+        assert_eq!(query.get("sort"), Some("name"));
+        assert_eq!(query.get("view"), Some(""));
+        assert_eq!(query.get("missing"), None);
+
+        let form = Query::parse("path=a.jpg&archive=zip&path=my+photo.jpg");
+        assert_eq!(form.get_all("path").collect::<Vec<_>>(), ["a.jpg", "my photo.jpg"]);
+    }
+
+    #[test]
+    fn test_byte_range_parse() {
+        let parse = |header| ByteRange::parse(Some(header), 100);
+        assert_eq!(ByteRange::parse(None, 100), ByteRange::Whole);
+        assert_eq!(parse("bytes=0-9"), ByteRange::Partial { start: 0, end: 9 });
+        assert_eq!(parse("bytes=90-"), ByteRange::Partial { start: 90, end: 99 });
+        assert_eq!(parse("bytes=90-500"), ByteRange::Partial { start: 90, end: 99 });
+        assert_eq!(parse("bytes=-10"), ByteRange::Partial { start: 90, end: 99 });
+        assert_eq!(parse("bytes=-500"), ByteRange::Partial { start: 0, end: 99 });
+        assert_eq!(parse("bytes=100-"), ByteRange::Unsatisfiable);
+        assert_eq!(parse("bytes=-0"), ByteRange::Unsatisfiable);
+        assert_eq!(parse("bytes=0-1,5-6"), ByteRange::Whole);
+        assert_eq!(parse("bytes=9-0"), ByteRange::Whole);
+        assert_eq!(parse("items=0-9"), ByteRange::Whole);
+        assert_eq!(ByteRange::parse(Some("bytes=-5"), 0), ByteRange::Unsatisfiable);
+    }
+
+    #[test]
+    fn test_response_bytes_sets_content_length() {
+        let response = Response::ok().header("Content-Type", "text/plain").body("hello").into_bytes();
+        assert_eq!(
+            String::from_utf8(response).unwrap(),
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 5\r\n\r\nhello"
+        );
+    }
+
+    #[test]
+    fn test_response_stream_without_length_is_chunked() {
+        let reader = Box::new(Cursor::new(b"streamed".to_vec()));
+        let response = Response::ok().stream(reader, None).into_bytes();
+        assert_eq!(
+            String::from_utf8(response).unwrap(),
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n8\r\nstreamed\r\n0\r\n\r\n"
+        );
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use tantivy::collector::TopDocs;
+use tantivy::directory::MmapDirectory;
+use tantivy::query::{BooleanQuery, Occur, Query, QueryParser, TermQuery};
+use tantivy::schema::{Facet, FacetOptions, Field, IndexRecordOption, STORED, STRING, Schema, TEXT, Value};
+use tantivy::{Index, IndexReader, IndexWriter, TantivyDocument, Term};
+
+use crate::format;
+use crate::grep;
+use crate::links::{Confinement, SymlinkPolicy};
+use crate::search::Walk;
+use crate::vfs::LocalFs;
+use crate::visibility::Visibility;
+
+/// How much memory the index writer may buffer before flushing a segment.
+const WRITER_MEMORY: usize = 50 * 1024 * 1024;
+
+struct Fields {
+    /// The path relative to the served root, with '/' separators.
+    path: Field,
+    name: Field,
+    content: Field,
+    /// The directory holding the file, so a search can be limited to a subtree.
+    dir: Field,
+    modified: Field,
+    size: Field,
+}
+
+impl Fields {
+    fn schema() -> (Schema, Fields) {
+        let mut builder = Schema::builder();
+        let fields = Fields {
+            path: builder.add_text_field("path", STRING | STORED),
+            name: builder.add_text_field("name", TEXT),
+            content: builder.add_text_field("content", TEXT),
+            dir: builder.add_facet_field("dir", FacetOptions::default()),
+            modified: builder.add_i64_field("modified", STORED),
+            size: builder.add_u64_field("size", STORED),
+        };
+        (builder.build(), fields)
+    }
+}
+
+/// What the search page says about how current the index is.
+#[derive(Debug, Clone, Default)]
+pub struct Status {
+    /// When the last scan of the tree finished.
+    pub updated: Option<SystemTime>,
+    /// Whether a scan is running right now.
+    pub scanning: bool,
+    pub files: u64,
+    /// Why the last scan failed, if it did.
+    pub error: Option<String>,
+}
+
+/// A ranked match from [`SearchIndex::search`].
+#[derive(Debug)]
+pub struct IndexHit {
+    /// The path relative to the served root, with '/' separators.
+    pub path: String,
+    pub score: f32,
+    pub size: u64,
+    pub modified: i64,
+}
+
+/// A full-text index over the names and text contents of the files under
+/// the served root, kept on disk in a state directory. It is brought up to
+/// date by rescanning the tree, which only re-reads files whose modification
+/// time or size changed since they were indexed.
+pub struct SearchIndex {
+    root: PathBuf,
+    /// Hidden and ignored files are left out of the index.
+    visibility: Visibility,
+    links: SymlinkPolicy,
+    /// Skipped when scanning, in case it lies inside the root.
+    state_dir: PathBuf,
+    index: Index,
+    reader: IndexReader,
+    writer: Mutex<IndexWriter>,
+    fields: Fields,
+    status: Mutex<Status>,
+}
+
+impl SearchIndex {
+    /// Opens the index in `state_dir`, creating it if needed.
+    pub fn open(root: &Path, state_dir: &Path, visibility: Visibility, links: SymlinkPolicy) -> io::Result<SearchIndex> {
+        fs::create_dir_all(state_dir)?;
+        let (schema, fields) = Fields::schema();
+        let directory = MmapDirectory::open(state_dir).map_err(io::Error::other)?;
+        let index = Index::open_or_create(directory, schema).map_err(io::Error::other)?;
+        let reader = index.reader().map_err(io::Error::other)?;
+        let writer = index.writer_with_num_threads(1, WRITER_MEMORY).map_err(io::Error::other)?;
+        let files = reader.searcher().num_docs();
+        Ok(SearchIndex {
+            root: root.to_path_buf(),
+            visibility,
+            links,
+            state_dir: fs::canonicalize(state_dir)?,
+            index,
+            reader,
+            writer: Mutex::new(writer),
+            fields,
+            status: Mutex::new(Status { files, ..Status::default() }),
+        })
+    }
+
+    pub fn status(&self) -> Status {
+        lock(&self.status).clone()
+    }
+
+    /// Rescans the tree every `interval` on a background thread.
+    pub fn spawn_updater(self: &Arc<Self>, interval: Duration) {
+        let index = Arc::clone(self);
+        thread::spawn(move || {
+            loop {
+                index.update();
+                thread::sleep(interval);
+            }
+        });
+    }
+
+    /// Scans the tree once, indexing new and changed files and dropping the
+    /// ones that are gone.
+    pub fn update(&self) {
+        lock(&self.status).scanning = true;
+        let result = self.scan();
+        let mut status = lock(&self.status);
+        status.scanning = false;
+        match result {
+            Ok(files) => {
+                status.updated = Some(SystemTime::now());
+                status.files = files;
+                status.error = None;
+            }
+            Err(err) => status.error = Some(err.to_string()),
+        }
+    }
+
+    fn scan(&self) -> tantivy::Result<u64> {
+        let mut indexed = self.indexed_files()?;
+        let mut writer = lock(&self.writer);
+        let mut changed = false;
+        let mut files = 0;
+
+        let confine = fs::canonicalize(&self.root)?;
+        let rules = self.visibility.rules(&LocalFs, &confine, "");
+        let mut walk = Walk::new(&LocalFs, &confine, &Confinement { root: confine.clone(), policy: self.links }, usize::MAX, rules);
+        while let Some(step) = walk.next_step(&LocalFs) {
+            if !step.metadata.is_file() || step.path.starts_with(&self.state_dir) {
+                continue;
+            }
+            let Some(path) = slash_path(&step.relative) else { continue };
+            files += 1;
+            let modified = step.metadata.modified.map_or(0, format::unix_seconds);
+            if indexed.remove(&path) == Some((modified, step.metadata.len)) {
+                continue;
+            }
+            writer.delete_term(Term::from_field_text(self.fields.path, &path));
+            let mut document = TantivyDocument::default();
+            document.add_text(self.fields.path, &path);
+            let name = path.rsplit('/').next().unwrap_or(&path);
+            document.add_text(self.fields.name, name);
+            if let Some(text) = grep::read_text(&LocalFs, &step.path, step.metadata.len) {
+                document.add_text(self.fields.content, text);
+            }
+            let dir = path.rsplit_once('/').map_or("", |(dir, _)| dir);
+            document.add_facet(self.fields.dir, Facet::from_path(dir.split('/').filter(|part| !part.is_empty())));
+            document.add_i64(self.fields.modified, modified);
+            document.add_u64(self.fields.size, step.metadata.len);
+            writer.add_document(document)?;
+            changed = true;
+        }
+        for path in indexed.keys() {
+            writer.delete_term(Term::from_field_text(self.fields.path, path));
+            changed = true;
+        }
+        if changed {
+            writer.commit()?;
+            self.reader.reload()?;
+        }
+        Ok(files)
+    }
+
+    /// The modification time and size of every file in the index, by path.
+    fn indexed_files(&self) -> tantivy::Result<HashMap<String, (i64, u64)>> {
+        let searcher = self.reader.searcher();
+        let mut files = HashMap::new();
+        for segment in searcher.segment_readers() {
+            let store = segment.get_store_reader(1)?;
+            for document in store.iter::<TantivyDocument>(segment.alive_bitset()) {
+                let document = document?;
+                let path = document.get_first(self.fields.path).and_then(|value| value.as_str());
+                let modified = document.get_first(self.fields.modified).and_then(|value| value.as_i64());
+                let size = document.get_first(self.fields.size).and_then(|value| value.as_u64());
+                if let (Some(path), Some(modified), Some(size)) = (path, modified, size) {
+                    files.insert(path.to_string(), (modified, size));
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    /// Finds up to `limit` files below the directory `within` (relative to
+    /// the root, empty for all of it) whose name or contents match `query`,
+    /// best matches first. Names weigh more than contents.
+    pub fn search(&self, query: &str, within: &str, limit: usize) -> tantivy::Result<Vec<IndexHit>> {
+        let mut parser = QueryParser::for_index(&self.index, vec![self.fields.name, self.fields.content]);
+        parser.set_field_boost(self.fields.name, 2.0);
+        let (mut query, _) = parser.parse_query_lenient(query);
+        let within = within.split('/').filter(|part| !part.is_empty()).collect::<Vec<_>>();
+        if !within.is_empty() {
+            let subtree = Term::from_facet(self.fields.dir, &Facet::from_path(within));
+            let subtree: Box<dyn Query> = Box::new(TermQuery::new(subtree, IndexRecordOption::Basic));
+            query = Box::new(BooleanQuery::new(vec![(Occur::Must, query), (Occur::Must, subtree)]));
+        }
+
+        let searcher = self.reader.searcher();
+        let mut hits = Vec::new();
+        for (score, address) in searcher.search(&query, &TopDocs::with_limit(limit))? {
+            let document = searcher.doc::<TantivyDocument>(address)?;
+            let Some(path) = document.get_first(self.fields.path).and_then(|value| value.as_str()) else { continue };
+            hits.push(IndexHit {
+                path: path.to_string(),
+                score,
+                size: document.get_first(self.fields.size).and_then(|value| value.as_u64()).unwrap_or(0),
+                modified: document.get_first(self.fields.modified).and_then(|value| value.as_i64()).unwrap_or(0),
+            });
+        }
+        Ok(hits)
+    }
+}
+
+/// Joins the components of a relative path with '/', as stored in the index.
+fn slash_path(relative: &Path) -> Option<String> {
+    let parts = relative
+        .components()
+        .map(|component| match component {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(parts.join("/"))
+}
+
+/// Locks a mutex, carrying on with the data if another thread panicked
+/// while holding it.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(hits: &[IndexHit]) -> Vec<&str> {
+        hits.iter().map(|hit| hit.path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_index_ranks_names_and_contents() {
+        let root = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("logs/2024")).unwrap();
+        fs::write(root.path().join("logs/2024/timeout.log"), "request finished").unwrap();
+        fs::write(root.path().join("logs/app.log"), "request timeout after 30s").unwrap();
+        fs::write(root.path().join("notes.txt"), "nothing here").unwrap();
+        fs::write(root.path().join("core.bin"), b"timeout\0").unwrap();
+
+        let index = SearchIndex::open(root.path(), state.path(), Visibility::default(), SymlinkPolicy::Inside).unwrap();
+        index.update();
+        let status = index.status();
+        assert_eq!(status.files, 4);
+        assert!(status.updated.is_some() && !status.scanning && status.error.is_none());
+
+        let hits = index.search("timeout", "", 10).unwrap();
+        assert_eq!(paths(&hits), vec!["logs/2024/timeout.log", "logs/app.log"]);
+        assert!(hits[0].score > hits[1].score);
+        assert_eq!(hits[1].size, 25);
+
+        assert_eq!(paths(&index.search("timeout", "logs/2024", 10).unwrap()), vec!["logs/2024/timeout.log"]);
+        assert!(index.search("timeout", "elsewhere", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_index_updates_incrementally() {
+        let root = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("a.txt"), "alpha").unwrap();
+        fs::write(root.path().join("b.txt"), "beta").unwrap();
+
+        let index = SearchIndex::open(root.path(), state.path(), Visibility::default(), SymlinkPolicy::Inside).unwrap();
+        index.update();
+        assert_eq!(paths(&index.search("alpha", "", 10).unwrap()), vec!["a.txt"]);
+
+        fs::remove_file(root.path().join("a.txt")).unwrap();
+        fs::write(root.path().join("b.txt"), "alpha beta").unwrap();
+        let later = SystemTime::now() + Duration::from_secs(10);
+        fs::File::options().write(true).open(root.path().join("b.txt")).unwrap().set_modified(later).unwrap();
+        index.update();
+        assert_eq!(paths(&index.search("alpha", "", 10).unwrap()), vec!["b.txt"]);
+        assert_eq!(index.status().files, 1);
+    }
+
+    #[test]
+    fn test_index_skips_its_own_state_directory() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("a.txt"), "alpha").unwrap();
+        let root = root.path().canonicalize().unwrap();
+        let visibility = Visibility { show_hidden: true, ..Visibility::default() };
+        let index = SearchIndex::open(&root, &root.join(".index"), visibility, SymlinkPolicy::Inside).unwrap();
+        index.update();
+        index.update();
+        assert_eq!(index.status().files, 1);
+    }
+
+    #[test]
+    fn test_index_persists_in_state_directory() {
+        let root = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("kept.txt"), "persistent").unwrap();
+        {
+            let index = SearchIndex::open(root.path(), state.path(), Visibility::default(), SymlinkPolicy::Inside).unwrap();
+            index.update();
+        }
+        let reopened = SearchIndex::open(root.path(), state.path(), Visibility::default(), SymlinkPolicy::Inside).unwrap();
+        assert_eq!(reopened.status().files, 1);
+        assert!(reopened.status().updated.is_none());
+        assert_eq!(paths(&reopened.search("persistent", "", 10).unwrap()), vec!["kept.txt"]);
+    }
+}
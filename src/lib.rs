@@ -0,0 +1,1986 @@
+mod app;
+mod archive;
+mod config;
+mod download;
+mod feed;
+mod filter;
+mod format;
+mod grep;
+mod http;
+mod index;
+mod links;
+mod listing;
+mod readme;
+mod search;
+mod site;
+mod sort;
+mod templates;
+pub mod vfs;
+mod visibility;
+
+use std::io::{Read, Seek, SeekFrom};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+pub use app::App;
+pub use config::{Config, USAGE};
+pub use links::SymlinkPolicy;
+
+use archive::ArchiveFs;
+use filter::NameFilter;
+use http::{ByteRange, Query, Response};
+use links::{Confinement, Refusal};
+use listing::{JsonListing, ListingFormat, ListingStream, Page};
+use sort::{Sort, SortKey, Sortable};
+use templates::{
+    AutoindexContext, AutoindexRow, Breadcrumb, Entry, ErrorContext, FindContext, FindResult, GrepContext, IndexStatus,
+    ListingContext, PreviewContext, SearchContext, SearchResult, url_value,
+};
+use vfs::{FileSystem, LocalFs};
+
+/// Decodes percent escapes as UTF-8 bytes, so `%E6%97%A5` becomes '日'.
+/// Sequences that are not valid UTF-8 come out as U+FFFD.
+fn url_decode(input: &str) -> String {
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut chars = input.chars();
+    let push = |bytes: &mut Vec<u8>, c: char| bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+
+    while let Some(ch) = chars.next() {
+        if ch == '%' {
+            let hex1 = chars.next().unwrap_or('0');
+            let hex2 = chars.next().unwrap_or('0');
+            if let Ok(byte) = u8::from_str_radix(&format!("{}{}", hex1, hex2), 16) {
+                bytes.push(byte);
+            } else {
+                push(&mut bytes, ch);
+                push(&mut bytes, hex1);
+                push(&mut bytes, hex2);
+            }
+        } else {
+            push(&mut bytes, ch);
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Percent-encodes everything but unreserved characters, writing characters
+/// outside ASCII as their UTF-8 bytes.
+fn url_encode(input: &str) -> String {
+    let mut encoded = String::with_capacity(input.len());
+    for c in input.chars() {
+        if c.is_ascii_alphanumeric() || "-_.~".contains(c) {
+            encoded.push(c);
+        } else {
+            for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        }
+    }
+    encoded
+}
+
+/// Extracts the decoded URL path from the request line, without its leading '/'.
+fn request_tail(request: &str) -> Option<String> {
+    // Parse HTTP request line: "GET /path HTTP/1.1"
+    let first_line = request.lines().next()?;
+    let parts: Vec<&str> = first_line.splitn(3, ' ').collect();
+    if parts.len() < 2 {
+        return None;
+    }
+    let path = parts[1].split_once('?').map_or(parts[1], |(path, _)| path);
+    Some(url_decode(path).trim_start_matches('/').to_string())
+}
+
+fn request_method(request: &str) -> &str {
+    request.split(' ').next().unwrap_or("")
+}
+
+/// What follows the blank line after the headers.
+fn request_body(request: &str) -> &str {
+    request.split_once("\r\n\r\n").map_or("", |(_, body)| body)
+}
+
+fn request_header<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+    request
+        .lines()
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .find(|(header, _)| header.trim().eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim())
+}
+
+/// The scheme and authority the client used to reach the server, from the
+/// Host header, for links that have to be absolute. Empty (leaving links
+/// root-relative) when the header is missing or does not look like a host.
+fn request_origin(request: &str) -> String {
+    let valid = |host: &str| {
+        !host.is_empty() && host.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '[' | ']'))
+    };
+    match request_header(request, "Host") {
+        Some(host) if valid(host) => format!("http://{}", host),
+        _ => String::new(),
+    }
+}
+
+/// What the handlers need from a request besides its path and query.
+#[derive(Debug, Default)]
+struct RequestInfo {
+    /// The listing format asked for; None when `?format=` names an unknown one.
+    format: Option<ListingFormat>,
+    /// See [`request_origin`].
+    origin: String,
+    /// The `Range` header, for files.
+    range: Option<String>,
+    /// The request path as sent, still percent-encoded, for redirects.
+    path: String,
+    /// The query string as sent, without the '?'.
+    query: String,
+}
+
+/// The path and query string of the request line, as sent.
+fn request_target(request: &str) -> (&str, &str) {
+    let target = request.lines().next().and_then(|line| line.split(' ').nth(1)).unwrap_or("");
+    target.split_once('?').unwrap_or((target, ""))
+}
+
+fn request_query(request: &str) -> Query {
+    Query::parse(request_target(request).1)
+}
+
+fn parse_requested_path(root: &Path, request: &str, policy: SymlinkPolicy) -> Result<PathBuf, Refusal> {
+    resolve_path(root, &request_tail(request).ok_or(Refusal::Invalid)?, policy)
+}
+
+/// Resolves `tail` below `root`, checking every symlink on the way against
+/// `policy` so that a refusal can name the link that caused it.
+fn resolve_path(root: &Path, tail: &str, policy: SymlinkPolicy) -> Result<PathBuf, Refusal> {
+    if tail.is_empty() {
+        return Ok(root.to_path_buf());
+    }
+    let requested_path = Path::new(tail);
+    if requested_path.is_absolute() {
+        return Err(Refusal::Invalid);
+    }
+    if requested_path.components().any(|c| matches!(c, Component::ParentDir)) {
+        return Err(Refusal::Invalid);
+    }
+    let normalize = |path: &Path| path.strip_prefix(r"\\?\").unwrap_or(path).to_path_buf();
+    let normalized_root = match root.canonicalize() {
+        Ok(canonical_root) => normalize(&canonical_root),
+        Err(_) => return Err(Refusal::Invalid),
+    };
+    let inside = |resolved: &Path| policy == SymlinkPolicy::Anywhere || normalize(resolved).starts_with(&normalized_root);
+
+    let mut current_path = root.to_path_buf();
+    let mut relative = PathBuf::new();
+    for component in requested_path.components() {
+        let Component::Normal(name) = component else { continue };
+        current_path.push(name);
+        relative.push(name);
+        let Ok(target) = std::fs::read_link(&current_path) else { continue };
+        let link = relative.display().to_string();
+        if policy == SymlinkPolicy::Never {
+            return Err(Refusal::NotFollowed { link });
+        }
+        match current_path.canonicalize() {
+            Ok(resolved) if inside(&resolved) => {}
+            Ok(_) => return Err(Refusal::OutsideRoot { link, target }),
+            Err(_) => return Err(Refusal::Broken { link, target }),
+        }
+    }
+    match current_path.canonicalize() {
+        Ok(resolved) if inside(&resolved) => Ok(resolved),
+        Ok(_) => Err(Refusal::Invalid),
+        Err(_) => Err(Refusal::Missing),
+    }
+}
+
+/// Splits a tail such as `artifacts/build.zip/logs/` into the archive on disk
+/// and the member path inside it. The archive name has to be followed by a
+/// '/', so that `artifacts/build.zip` on its own still downloads the file.
+fn split_archive_path(fs: &dyn FileSystem, root: &Path, tail: &str, policy: SymlinkPolicy) -> Option<(PathBuf, PathBuf)> {
+    for (index, _) in tail.match_indices('/') {
+        let prefix = &tail[..index];
+        if !archive::is_archive(Path::new(prefix)) {
+            continue;
+        }
+        let Ok(archive_path) = resolve_path(root, prefix, policy) else { continue };
+        if !fs.metadata(&archive_path).is_ok_and(|m| m.is_file()) {
+            continue;
+        }
+        let member = Path::new(&tail[index + 1..]);
+        if member.components().any(|c| !matches!(c, Component::Normal(_))) {
+            return None;
+        }
+        return Some((archive_path, member.to_path_buf()));
+    }
+    None
+}
+
+fn get_mime_type(file_path: &Path) -> &'static str {
+    if let Some(extension) = file_path.extension().and_then(|ext| ext.to_str()) {
+        match extension.to_lowercase().as_str() {
+            // Image types
+            "jpg" | "jpeg" => "image/jpeg",
+            "png" => "image/png",
+            "gif" => "image/gif",
+            "bmp" => "image/bmp",
+            "webp" => "image/webp",
+            "svg" => "image/svg+xml",
+            "ico" => "image/x-icon",
+            "tiff" | "tif" => "image/tiff",
+            // Video types
+            "mp4" => "video/mp4",
+            "webm" => "video/webm",
+            "ogg" => "video/ogg",
+            "mov" => "video/quicktime",
+            "avi" => "video/x-msvideo",
+            "mkv" => "video/x-matroska",
+            "wmv" => "video/x-ms-wmv",
+            "flv" => "video/x-flv",
+            "m4v" => "video/x-m4v",
+            // Audio types
+            "mp3" => "audio/mpeg",
+            "m4a" => "audio/mp4",
+            "aac" => "audio/aac",
+            "oga" | "opus" => "audio/ogg",
+            "wav" => "audio/wav",
+            "flac" => "audio/flac",
+            _ => "text/plain",
+        }
+    } else {
+        "text/plain"
+    }
+}
+
+fn is_image_file(file_path: &Path) -> bool {
+    if let Some(extension) = file_path.extension().and_then(|ext| ext.to_str()) {
+        matches!(
+            extension.to_lowercase().as_str(),
+            "jpg" | "jpeg" | "png" | "gif" | "bmp" | "webp" | "svg" | "ico" | "tiff" | "tif"
+        )
+    } else {
+        false
+    }
+}
+
+fn is_video_file(file_path: &Path) -> bool {
+    if let Some(extension) = file_path.extension().and_then(|ext| ext.to_str()) {
+        matches!(
+            extension.to_lowercase().as_str(),
+            "mp4" | "webm" | "ogg" | "mov" | "avi" | "mkv" | "wmv" | "flv" | "m4v"
+        )
+    } else {
+        false
+    }
+}
+
+fn is_audio_file(file_path: &Path) -> bool {
+    if let Some(extension) = file_path.extension().and_then(|ext| ext.to_str()) {
+        matches!(extension.to_lowercase().as_str(), "mp3" | "m4a" | "aac" | "oga" | "opus" | "wav" | "flac")
+    } else {
+        false
+    }
+}
+
+/// Text files up to this size are shown inline on their preview page.
+const PREVIEW_TEXT_LIMIT: u64 = 1024 * 1024;
+
+fn error_response(app: &App, status: &'static str, message: &str) -> Response {
+    match app.templates.render("error.html", ErrorContext { status, message }) {
+        Ok(html) => Response::new(status).header("Content-Type", "text/html; charset=utf-8").body(html),
+        Err(_) => Response::new(status).body(message.to_string()),
+    }
+}
+
+fn html_response(app: &App, template: &str, context: impl serde::Serialize) -> Response {
+    match app.templates.render(template, context) {
+        Ok(html) => Response::ok().header("Content-Type", "text/html; charset=utf-8").body(html),
+        Err(err) => Response::new("500 Internal Server Error").body(format!("Error rendering {}: {}", template, err)),
+    }
+}
+
+/// The URL of the directory containing `tail`, with a trailing slash so that,
+/// from inside an archive, going up to its root lists the archive instead of
+/// downloading it.
+fn parent_url(tail: &str) -> String {
+    let parent = tail.trim_end_matches('/').rsplit_once('/').map(|(base, _)| base).unwrap_or("");
+    if parent.is_empty() { "/".to_string() } else { format!("/{}/", url_encode(parent)) }
+}
+
+/// The URL of the directory `tail` with every segment encoded separately,
+/// e.g. `/docs/sub%20dir/`, so that relative links resolve inside it.
+fn slash_url(tail: &str) -> String {
+    tail.split('/').filter(|segment| !segment.is_empty()).map(|segment| format!("/{}", url_encode(segment))).collect::<String>() + "/"
+}
+
+/// A permanent redirect to `path`, keeping the request's query string.
+fn redirect(path: &str, query: &str) -> Response {
+    let location = if query.is_empty() { path.to_string() } else { format!("{}?{}", path, query) };
+    Response::new("301 Moved Permanently").header("Location", location)
+}
+
+/// The path a request for a directory (`is_dir`) or a file should have
+/// used, when `path` is not already it: directory paths end in a single
+/// '/', file paths in none. An encoded `%2F` counts as a slash, since
+/// listings encode the slashes inside entry URLs.
+fn canonical_path(path: &str, is_dir: bool) -> Option<String> {
+    let mut trimmed = path;
+    while let Some(rest) = ["/", "%2F", "%2f"].iter().find_map(|slash| trimmed.strip_suffix(slash)) {
+        trimmed = rest;
+    }
+    let canonical = if is_dir { format!("{}/", trimmed) } else { trimmed.to_string() };
+    (canonical != path).then_some(canonical)
+}
+
+fn breadcrumbs(tail: &str) -> Vec<Breadcrumb> {
+    let mut crumbs = vec![Breadcrumb { name: "/".to_string(), url: url_value("/".to_string()) }];
+    let mut path = String::new();
+    for segment in tail.split('/').filter(|segment| !segment.is_empty()) {
+        if !path.is_empty() {
+            path.push('/');
+        }
+        path.push_str(segment);
+        crumbs.push(Breadcrumb { name: segment.to_string(), url: url_value(format!("/{}/", url_encode(&path))) });
+    }
+    crumbs
+}
+
+/// The directory `tail` as shown at the top of search pages, e.g.
+/// `/docs/sub`, and the URL of its listing.
+fn location(tail: &str) -> (String, minijinja::Value) {
+    let crumbs = breadcrumbs(tail);
+    let path = crumbs.iter().skip(1).map(|crumb| format!("/{}", crumb.name)).collect();
+    let listing_url = crumbs.last().map_or_else(|| url_value("/".to_string()), |crumb| crumb.url.clone());
+    (path, listing_url)
+}
+
+/// Sends a file, or the part of it `range` (a `Range` header) asks for when
+/// the backend can seek, which media players rely on to skip around.
+fn generate_file_response(app: &App, fs: &dyn FileSystem, file_path: &Path, range: Option<&str>) -> Response {
+    let site_type = app.site.as_ref().and_then(|_| site::mime_type(file_path));
+    let mime_type = site_type.unwrap_or_else(|| get_mime_type(file_path));
+
+    if let Ok((mut file, len)) = fs.metadata(file_path).and_then(|metadata| Ok((fs.open_seekable(file_path)?, metadata.len))) {
+        let response = Response::ok().header("Content-Type", mime_type).header("Accept-Ranges", "bytes");
+        return match ByteRange::parse(range, len) {
+            ByteRange::Whole => response.stream(file, Some(len)),
+            ByteRange::Partial { start, end } => match file.seek(SeekFrom::Start(start)) {
+                Ok(_) => Response::new("206 Partial Content")
+                    .header("Content-Type", mime_type)
+                    .header("Accept-Ranges", "bytes")
+                    .header("Content-Range", format!("bytes {}-{}/{}", start, end, len))
+                    .stream(Box::new(file.take(end - start + 1)), Some(end - start + 1)),
+                Err(_) => error_response(app, "500 Internal Server Error", "Error reading file"),
+            },
+            ByteRange::Unsatisfiable => {
+                Response::new("416 Range Not Satisfiable").header("Content-Range", format!("bytes */{}", len))
+            }
+        };
+    }
+    match fs.metadata(file_path).and_then(|metadata| Ok((fs.open(file_path)?, metadata.len))) {
+        Ok((reader, len)) => Response::ok().header("Content-Type", mime_type).stream(reader, Some(len)),
+        Err(_) if is_image_file(file_path) || is_video_file(file_path) => error_response(
+            app,
+            "500 Internal Server Error",
+            &format!("Error reading {} file", if is_image_file(file_path) { "image" } else { "video" }),
+        ),
+        Err(_) => error_response(app, "500 Internal Server Error", "Error reading file"),
+    }
+}
+
+fn generate_preview_response(app: &App, fs: &dyn FileSystem, file_path: &Path, tail: &str) -> Response {
+    let metadata = match fs.metadata(file_path) {
+        Ok(metadata) => metadata,
+        Err(_) => return error_response(app, "500 Internal Server Error", "Error reading file"),
+    };
+    let kind = if is_image_file(file_path) {
+        "image"
+    } else if is_video_file(file_path) {
+        "video"
+    } else if get_mime_type(file_path).starts_with("text/") {
+        "text"
+    } else {
+        "other"
+    };
+    let text = if kind == "text" && metadata.len <= PREVIEW_TEXT_LIMIT {
+        let mut contents = Vec::new();
+        fs.open(file_path)
+            .and_then(|reader| reader.take(PREVIEW_TEXT_LIMIT).read_to_end(&mut contents))
+            .ok()
+            .and_then(|_| String::from_utf8(contents).ok())
+    } else {
+        None
+    };
+    let context = PreviewContext {
+        name: file_path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+        url: url_value(format!("/{}", url_encode(tail))),
+        parent_url: url_value(parent_url(tail)),
+        mime_type: get_mime_type(file_path),
+        kind,
+        size: metadata.len,
+        size_human: format::human_size(metadata.len),
+        modified: metadata.modified.map(format::format_timestamp),
+        text,
+    };
+    html_response(app, "preview.html", context)
+}
+
+/// A directory entry as read, before it is formatted into a listing row.
+/// Huge directories are sorted in this form, and only the rows of the page
+/// being sent are formatted.
+struct RawEntry {
+    path: PathBuf,
+    name: String,
+    metadata: Option<vfs::Metadata>,
+    /// The target, when the entry is a symlink.
+    link: Option<PathBuf>,
+}
+
+impl RawEntry {
+    fn new(entry: vfs::DirEntry) -> Option<RawEntry> {
+        let name = entry.path.file_name()?.to_str()?.to_string();
+        Some(RawEntry { path: entry.path, name, metadata: entry.metadata, link: entry.link })
+    }
+
+    fn is_dir(&self) -> bool {
+        self.metadata.as_ref().is_some_and(|m| m.is_dir())
+    }
+}
+
+impl Sortable for RawEntry {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn is_dir(&self) -> bool {
+        RawEntry::is_dir(self)
+    }
+
+    fn size(&self) -> Option<u64> {
+        self.metadata.as_ref().filter(|m| !m.is_dir()).map(|m| m.len)
+    }
+
+    fn modified(&self) -> Option<i64> {
+        self.metadata.as_ref().and_then(|m| m.modified).map(format::unix_seconds)
+    }
+
+    fn mime_type(&self) -> Option<&'static str> {
+        (!RawEntry::is_dir(self)).then(|| get_mime_type(&self.path))
+    }
+}
+
+/// Formats a directory entry as a listing row, with times relative to `now`.
+/// With `hidden_root` set, link targets are shown as seen from the root.
+fn make_entry(raw: RawEntry, tail: &str, now: std::time::SystemTime, hidden_root: Option<&Path>) -> Entry {
+    let RawEntry { path, name, metadata, link } = raw;
+    let rel_path = Path::new(tail).join(&name);
+    let is_dir = metadata.as_ref().is_some_and(|m| m.is_dir());
+    let size = metadata.as_ref().filter(|_| !is_dir).map(|m| m.len);
+    let modified = metadata.as_ref().and_then(|m| m.modified);
+    Entry {
+        url: url_value(format!("/{}{}", url_encode(&rel_path.display().to_string()), if is_dir { "/" } else { "" })),
+        name,
+        is_dir,
+        is_archive: !is_dir && archive::is_archive(&path),
+        size,
+        size_human: size.map(format::human_size),
+        modified: modified.map(format::unix_seconds),
+        modified_display: modified.map(format::format_timestamp),
+        modified_iso: modified.map(format::format_rfc3339),
+        modified_relative: modified.map(|modified| format::relative_time(modified, now)),
+        mime_type: (!is_dir).then(|| get_mime_type(&path)),
+        permissions: metadata.as_ref().and_then(|m| m.permissions).map(|mode| format::permissions(mode, is_dir)),
+        broken_link: link.is_some() && metadata.is_none(),
+        link_target: link.and_then(|target| match hidden_root {
+            Some(root) => links::target_from_root(root, &rel_path, &target),
+            None => Some(target.display().to_string()),
+        }),
+    }
+}
+
+/// Whether `resolved`, a path with its symlinks resolved, is hidden in the
+/// place it really is, so a link such as `cfg -> .secret` does not serve
+/// what the hidden name is kept from.
+fn hides_target(app: &App, fs: &dyn FileSystem, resolved: &Path) -> bool {
+    let Ok(root) = fs.canonicalize(&app.root) else { return false };
+    let Ok(relative) = resolved.strip_prefix(&root) else { return false };
+    let tail = relative.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+    app.visibility.hides(fs, &app.root, &tail)
+}
+
+/// The served root when `--hide-path` keeps its location private, so link
+/// targets are shown relative to it rather than as written.
+fn hidden_root(app: &App) -> Option<&Path> {
+    app.hide_path.then_some(app.root.as_path())
+}
+
+/// Everything on a listing page except its rows and their counts, which
+/// needs nothing from the directory itself.
+fn listing_frame(app: &App, dir_path: &Path, tail: &str, sort: Sort, filter: &NameFilter) -> ListingContext {
+    let filter_suffix = filter.to_query_suffix();
+    let path = dir_path.display().to_string();
+    let is_root = dir_path.parent().is_none() || dir_path == app.root;
+    let unsorted = Sort { key: SortKey::Unsorted, ..sort };
+    ListingContext {
+        long_path: path.contains(r"\\?\"),
+        path: (!app.hide_path).then_some(path),
+        parent_url: (!is_root).then(|| url_value(parent_url(tail))),
+        breadcrumbs: breadcrumbs(tail),
+        entries: Vec::new(),
+        total_entries: 0,
+        matched_entries: 0,
+        filtered: filter.is_active(),
+        sort,
+        sort_links: SortKey::ALL
+            .iter()
+            .map(|&key| (key.as_str(), url_value(sort.toggled(key).to_query() + &filter_suffix)))
+            .chain([(SortKey::Unsorted.as_str(), url_value(unsorted.to_query() + &filter_suffix))])
+            .collect(),
+        dirs_toggle_url: url_value(Sort { dirs_first: !sort.dirs_first, ..sort }.to_query() + &filter_suffix),
+        indexed: app.index.is_some(),
+        has_media: false,
+        has_subdirs: false,
+        playlist_url: url_value(sort.to_query() + &filter_suffix + "&format=m3u8"),
+        filter: filter.pattern.clone(),
+        q: filter.q.clone(),
+        rows: url_value(LISTING_ROWS_MARKER.to_string()),
+        readme: None,
+    }
+}
+
+/// Reads, filters and sorts a directory into what every listing format is
+/// rendered from, keeping only the rows on `page` when one is given.
+/// Failures come back as the response to send instead.
+fn listing_context(
+    app: &App,
+    fs: &dyn FileSystem,
+    dir_path: &Path,
+    tail: &str,
+    query: &Query,
+    page: Option<Page>,
+) -> Result<ListingContext, Response> {
+    let Ok(filter) = NameFilter::from_query(query) else {
+        return Err(error_response(app, "400 Bad Request", "Invalid filter pattern"));
+    };
+    let Ok(scan) = fs.scan_dir(dir_path) else {
+        return Err(error_response(app, "500 Internal Server Error", "Error reading directory"));
+    };
+
+    let rules = app.visibility.rules(fs, &app.root, tail);
+    let sort = Sort::from_query(query);
+    let mut context = listing_frame(app, dir_path, tail, sort, &filter);
+    let Page { offset, limit } = page.unwrap_or(Page { offset: 0, limit: usize::MAX });
+    let matched = scan
+        .filter_map(RawEntry::new)
+        .filter(|raw| !rules.hides(&raw.name, raw.is_dir()))
+        .inspect(|_| context.total_entries += 1)
+        .filter(|raw| filter.matches(&raw.name))
+        .inspect(|raw| {
+            context.matched_entries += 1;
+            context.has_media |= !raw.is_dir() && feed::is_media_file(&raw.path);
+            context.has_subdirs |= raw.is_dir();
+        });
+    // Only the entries up to the end of the page are kept while the rest
+    // of the directory is read, so memory stays bounded by the page.
+    let first = sort.first(matched, offset.saturating_add(limit));
+    let now = std::time::SystemTime::now();
+    context.entries = first.into_iter().skip(offset).map(|raw| make_entry(raw, tail, now, hidden_root(app))).collect();
+    Ok(context)
+}
+
+/// Stands in for the streamed rows while `listing.html` is rendered, so the
+/// page can be split around them.
+const LISTING_ROWS_MARKER: &str = "<!-- listing rows -->";
+
+/// Sends one page of the listing, row by row with chunked encoding. Sorted
+/// listings have to read the whole directory first, but keep only the
+/// entries up to the end of the page and format only its rows; in directory
+/// order (`?sort=none`) rows are sent as the directory is read, and reading
+/// stops at the end of the page.
+fn generate_directory_response(app: &App, fs: &dyn FileSystem, dir_path: &Path, tail: &str, query: &Query) -> Response {
+    let Ok(filter) = NameFilter::from_query(query) else {
+        return error_response(app, "400 Bad Request", "Invalid filter pattern");
+    };
+    let page = Page::from_query(query, app.page_size);
+    let sort = Sort::from_query(query);
+    let base_query = sort.to_query() + &filter.to_query_suffix();
+    let rules = app.visibility.rules(fs, &app.root, tail);
+    let (mut context, rows, matched): (_, Box<dyn Iterator<Item = Entry> + Send>, _) = if sort.key == SortKey::Unsorted {
+        let Ok(scan) = fs.scan_dir(dir_path) else {
+            return error_response(app, "500 Internal Server Error", "Error reading directory");
+        };
+        let context = listing_frame(app, dir_path, tail, sort, &filter);
+        let row_rules = rules.clone();
+        let tail = tail.to_string();
+        let hidden_root = hidden_root(app).map(Path::to_path_buf);
+        let now = std::time::SystemTime::now();
+        let rows = scan
+            .filter_map(RawEntry::new)
+            .filter(move |raw| !row_rules.hides(&raw.name, raw.is_dir()) && filter.matches(&raw.name))
+            .skip(page.offset)
+            .map(move |raw| make_entry(raw, &tail, now, hidden_root.as_deref()));
+        (context, Box::new(rows), None)
+    } else {
+        let mut context = match listing_context(app, fs, dir_path, tail, query, Some(page)) {
+            Ok(context) => context,
+            Err(response) => return response,
+        };
+        let matched = context.matched_entries;
+        let entries = std::mem::take(&mut context.entries);
+        (context, Box::new(entries.into_iter()), Some(matched))
+    };
+    context.readme = confinement(app, fs)
+        .ok()
+        .and_then(|confine| readme::render(fs, dir_path, &slash_url(tail), &rules, &confine));
+
+    let html = match app.templates.render("listing.html", &context) {
+        Ok(html) => html,
+        Err(err) => return Response::new("500 Internal Server Error").body(format!("Error rendering listing.html: {}", err)),
+    };
+    let Some((prefix, suffix)) = html.split_once(LISTING_ROWS_MARKER) else {
+        // An override template without the rows marker gets the whole page at once.
+        context.entries = rows.take(page.limit).collect();
+        return html_response(app, "listing.html", context);
+    };
+    let page_html = (prefix.to_string(), suffix.to_string());
+    let stream = ListingStream::new(rows, (page, app.page_size), matched, base_query, app.templates.clone(), page_html);
+    Response::ok().header("Content-Type", "text/html; charset=utf-8").stream(Box::new(stream), None)
+}
+
+fn generate_json_listing(app: &App, fs: &dyn FileSystem, dir_path: &Path, tail: &str, query: &Query) -> Response {
+    let context = match listing_context(app, fs, dir_path, tail, query, None) {
+        Ok(context) => context,
+        Err(response) => return response,
+    };
+    match serde_json::to_string_pretty(&JsonListing::new(&context)) {
+        Ok(json) => Response::ok().header("Content-Type", "application/json").body(json),
+        Err(_) => error_response(app, "500 Internal Server Error", "Error rendering listing"),
+    }
+}
+
+/// Serves the listing as `?format=txt` or `?format=csv`. The plain text
+/// URLs are absolute, built on `origin`, so they can be fed straight to a
+/// downloader.
+fn generate_text_listing(
+    app: &App,
+    fs: &dyn FileSystem,
+    dir_path: &Path,
+    tail: &str,
+    query: &Query,
+    format: ListingFormat,
+    origin: &str,
+) -> Response {
+    let context = match listing_context(app, fs, dir_path, tail, query, None) {
+        Ok(context) => context,
+        Err(response) => return response,
+    };
+    match format {
+        ListingFormat::Csv => Response::ok().header("Content-Type", "text/csv; charset=utf-8").body(listing::to_csv(&context)),
+        _ => Response::ok().header("Content-Type", "text/plain; charset=utf-8").body(listing::to_txt(&context, origin)),
+    }
+}
+
+/// Serves the listing in the shape of Apache's mod_autoindex output: a
+/// `<pre>` block of links with slash-separated hrefs that mirroring tools
+/// know how to follow.
+fn generate_autoindex_listing(app: &App, fs: &dyn FileSystem, dir_path: &Path, tail: &str, query: &Query) -> Response {
+    const NAME_WIDTH: usize = 23;
+    let context = match listing_context(app, fs, dir_path, tail, query, None) {
+        Ok(context) => context,
+        Err(response) => return response,
+    };
+    let segments = tail.split('/').filter(|segment| !segment.is_empty()).collect::<Vec<_>>();
+    let dir_url = slash_url(tail);
+
+    let display_len = |entry: &Entry| entry.name.chars().count() + usize::from(entry.is_dir);
+    let width = context.entries.iter().map(display_len).max().unwrap_or(0).max(NAME_WIDTH);
+    let padding = |len: usize| " ".repeat(width - len);
+    let rows = context
+        .entries
+        .iter()
+        .map(|entry| AutoindexRow {
+            href: url_value(format!("{}{}{}", dir_url, url_encode(&entry.name), if entry.is_dir { "/" } else { "" })),
+            name: entry.name.clone(),
+            is_dir: entry.is_dir,
+            padding: padding(display_len(entry)),
+            modified: format!("{:<16}", entry.modified_display.as_ref().map_or("-", |display| &display[..16])),
+            size: format!("{:>5}", entry.size.filter(|_| !entry.is_dir).map_or("-".to_string(), listing::autoindex_size)),
+        })
+        .collect();
+    let autoindex = AutoindexContext {
+        parent_url: context.parent_url.is_some().then(|| url_value(slash_url(&segments[..segments.len().saturating_sub(1)].join("/")))),
+        segments: segments.iter().map(|segment| segment.to_string()).collect(),
+        header_padding: padding("Name".len()),
+        parent_padding: padding("Parent Directory".len()),
+        rows,
+    };
+    html_response(app, "autoindex.html", autoindex)
+}
+
+/// The served root as `fs` sees it, with the symlink policy, for walking
+/// the tree below a directory.
+fn confinement(app: &App, fs: &dyn FileSystem) -> std::io::Result<Confinement> {
+    Ok(Confinement { root: fs.canonicalize(&app.root)?, policy: app.symlinks })
+}
+
+/// Serves `?format=atom` and `?format=rss`: the most recently modified
+/// files in the directory, or below it with `&recursive`, newest first. The
+/// Atom feed holds the latest few files of any kind, the RSS podcast feed
+/// every audio and video file up to the search result limit.
+fn generate_feed(app: &App, fs: &dyn FileSystem, dir_path: &Path, tail: &str, query: &Query, info: &RequestInfo) -> Response {
+    let Ok(confine) = confinement(app, fs) else {
+        return error_response(app, "500 Internal Server Error", "Error reading directory");
+    };
+    let podcast = info.format == Some(ListingFormat::Rss);
+    let default_count = if podcast { app.search.max_results } else { feed::DEFAULT_ENTRIES };
+    let count = query.get("limit").and_then(|limit| limit.parse().ok()).unwrap_or(default_count).min(app.search.max_results);
+    let recursive = query.contains("recursive");
+    let limits = search::Limits { max_depth: if recursive { app.search.max_depth } else { 0 }, ..app.search };
+    let files = feed::recent_files(fs, dir_path, &confine, app.visibility.rules(fs, &app.root, tail), limits, count, |path| !podcast || feed::is_media_file(path));
+
+    let mut self_query = format!("?format={}", if podcast { "rss" } else { "atom" });
+    if recursive {
+        self_query.push_str("&recursive");
+    }
+    if count != default_count {
+        self_query.push_str(&format!("&limit={}", count));
+    }
+    let channel = feed::Channel { origin: &info.origin, tail, self_query };
+    if podcast {
+        Response::ok().header("Content-Type", "application/rss+xml; charset=utf-8").body(feed::rss(&channel, &files))
+    } else {
+        Response::ok().header("Content-Type", "application/atom+xml; charset=utf-8").body(feed::atom(&channel, &files))
+    }
+}
+
+/// Serves `?format=m3u8`: a playlist of the audio and video files in the
+/// directory, or below it with `&recursive`, in the listing's sort order and
+/// narrowed by its filter.
+fn generate_playlist(app: &App, fs: &dyn FileSystem, dir_path: &Path, tail: &str, query: &Query, origin: &str) -> Response {
+    let Ok(filter) = NameFilter::from_query(query) else {
+        return error_response(app, "400 Bad Request", "Invalid filter pattern");
+    };
+    let Ok(confine) = confinement(app, fs) else {
+        return error_response(app, "500 Internal Server Error", "Error reading directory");
+    };
+    let limits = search::Limits { max_depth: if query.contains("recursive") { app.search.max_depth } else { 0 }, ..app.search };
+    let wanted = |path: &Path| {
+        feed::is_media_file(path) && path.file_name().and_then(|name| name.to_str()).is_some_and(|name| filter.matches(name))
+    };
+    let mut files = feed::matching_files(fs, dir_path, &confine, app.visibility.rules(fs, &app.root, tail), limits, wanted);
+    feed::sort_files(&mut files, Sort::from_query(query));
+
+    let channel = feed::Channel { origin, tail, self_query: String::new() };
+    let name = tail.trim_end_matches('/').rsplit('/').next().filter(|name| !name.is_empty()).unwrap_or("playlist");
+    Response::ok()
+        .header("Content-Type", "audio/x-mpegurl; charset=utf-8")
+        .header("Content-Disposition", download::attachment(&format!("{}.m3u8", name)))
+        .body(feed::m3u(&channel, &files))
+}
+
+fn generate_search_response(app: &App, fs: &dyn FileSystem, dir_path: &Path, tail: &str, query: &Query) -> Response {
+    let Ok(filter) = NameFilter::from_query(query) else {
+        return error_response(app, "400 Bad Request", "Invalid filter pattern");
+    };
+    let Ok(confine) = confinement(app, fs) else {
+        return error_response(app, "500 Internal Server Error", "Error reading directory");
+    };
+    let max_depth = query.get("depth").and_then(|depth| depth.parse().ok()).map_or(app.search.max_depth, |depth: usize| {
+        depth.min(app.search.max_depth)
+    });
+    let limits = search::Limits { max_depth, ..app.search };
+
+    let outcome = if filter.is_active() {
+        search::find(fs, dir_path, &confine, app.visibility.rules(fs, &app.root, tail), &filter, limits)
+    } else {
+        search::Outcome::default()
+    };
+    let results = outcome
+        .hits
+        .into_iter()
+        .map(|hit| SearchResult {
+            path: hit.relative.display().to_string(),
+            url: url_value(format!(
+                "/{}{}",
+                url_encode(&Path::new(tail).join(&hit.relative).display().to_string()),
+                if hit.is_dir { "/" } else { "" }
+            )),
+            is_dir: hit.is_dir,
+            size_human: hit.size.map(format::human_size),
+        })
+        .collect();
+
+    let (path, listing_url) = location(tail);
+    let context = SearchContext {
+        path,
+        listing_url,
+        searched: filter.is_active(),
+        q: filter.q,
+        filter: filter.pattern,
+        depth: max_depth,
+        results,
+        stopped: outcome.stopped,
+        depth_limited: outcome.depth_limited,
+        max_results: limits.max_results,
+        timeout_secs: limits.timeout.as_secs(),
+    };
+    html_response(app, "search.html", context)
+}
+
+fn generate_find_response(app: &App, tail: &str, query: &Query) -> Response {
+    let Some(index) = &app.index else {
+        return error_response(app, "404 Not Found", "No search index is kept for this server");
+    };
+    let terms = query.get("find").filter(|terms| !terms.trim().is_empty());
+    let hits = match terms.map(|terms| index.search(terms, tail, app.search.max_results)) {
+        Some(Ok(hits)) => hits,
+        Some(Err(_)) => return error_response(app, "500 Internal Server Error", "Error searching the index"),
+        None => Vec::new(),
+    };
+
+    let now = std::time::SystemTime::now();
+    let within = tail.trim_matches('/');
+    let results = hits
+        .into_iter()
+        .map(|hit| {
+            let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(hit.modified.max(0) as u64);
+            FindResult {
+                path: hit.path.strip_prefix(within).map_or(hit.path.as_str(), |rest| rest.trim_start_matches('/')).to_string(),
+                url: url_value(format!("/{}", url_encode(&hit.path))),
+                score: hit.score,
+                size_human: format::human_size(hit.size),
+                modified_display: Some(format::format_timestamp(modified)),
+                modified_iso: Some(format::format_rfc3339(modified)),
+                modified_relative: Some(format::relative_time(modified, now)),
+            }
+        })
+        .collect();
+
+    let status = index.status();
+    let age = status.updated.and_then(|updated| now.duration_since(updated).ok());
+    let (path, listing_url) = location(tail);
+    let context = FindContext {
+        path,
+        listing_url,
+        query: terms.map(str::to_string),
+        results,
+        index: IndexStatus {
+            fresh: status.error.is_none() && age.is_some_and(|age| age <= app.index_interval * 2),
+            scanning: status.scanning,
+            files: status.files,
+            updated_display: status.updated.map(format::format_timestamp),
+            updated_relative: status.updated.map(|updated| format::relative_time(updated, now)),
+            error: status.error,
+        },
+    };
+    html_response(app, "find.html", context)
+}
+
+/// Stands in for the streamed matches while `grep.html` is rendered, so the
+/// page can be split around them.
+const GREP_MATCHES_MARKER: &str = "<!-- grep matches -->";
+
+fn generate_grep_response<F: FileSystem + 'static>(
+    app: &App,
+    fs: F,
+    dir_path: &Path,
+    tail: &str,
+    query: &Query,
+    info: &RequestInfo,
+) -> Response {
+    if !fs.metadata(dir_path).is_ok_and(|m| m.is_dir()) {
+        return serve_path(app, &fs, dir_path, tail, query, info);
+    }
+    let pattern = query.get("grep").filter(|pattern| !pattern.is_empty());
+    let (path, listing_url) = location(tail);
+    let context = GrepContext {
+        path,
+        listing_url,
+        pattern: pattern.map(str::to_string),
+        regex: query.contains("regex"),
+        icase: query.contains("icase"),
+        file_limit: format::human_size(grep::FILE_LIMIT),
+        matches: url_value(GREP_MATCHES_MARKER.to_string()),
+    };
+    let Some(pattern) = pattern else { return html_response(app, "grep.html", context) };
+    let Ok(regex) = grep::build_pattern(pattern, context.regex, context.icase) else {
+        return error_response(app, "400 Bad Request", "Invalid regular expression");
+    };
+    let Ok(confine) = confinement(app, &fs) else {
+        return error_response(app, "500 Internal Server Error", "Error reading directory");
+    };
+    let page = match app.templates.render("grep.html", &context) {
+        Ok(html) => html,
+        Err(err) => return Response::new("500 Internal Server Error").body(format!("Error rendering grep.html: {}", err)),
+    };
+    let Some((prefix, suffix)) = page.split_once(GREP_MATCHES_MARKER) else {
+        return html_response(app, "grep.html", context);
+    };
+
+    let walk = search::Walk::new(&fs, dir_path, &confine, app.search.max_depth, app.visibility.rules(&fs, &app.root, tail));
+    let page = (prefix.to_string(), suffix.to_string());
+    let stream = grep::GrepStream::new(fs, walk, regex, app.search, app.templates.clone(), tail, page);
+    Response::ok().header("Content-Type", "text/html; charset=utf-8").stream(Box::new(stream), None)
+}
+
+/// Serves `?archive=zip`, `tar`, `tar.gz` or `tar.zst`: the directory and
+/// everything below it that is not hidden, packed into an archive while it
+/// is sent, or only the `selection` of it. Files are served as usual.
+fn generate_archive_response<F: FileSystem + 'static>(
+    app: &App,
+    fs: F,
+    dir_path: &Path,
+    tail: &str,
+    query: &Query,
+    info: &RequestInfo,
+    selection: Option<Vec<PathBuf>>,
+) -> Response {
+    if !fs.metadata(dir_path).is_ok_and(|m| m.is_dir()) {
+        return serve_path(app, &fs, dir_path, tail, query, info);
+    }
+    let Ok(confine) = confinement(app, &fs) else {
+        return error_response(app, "500 Internal Server Error", "Error reading directory");
+    };
+    let Some(format) = query.get("archive").and_then(download::ArchiveFormat::parse) else {
+        return error_response(app, "400 Bad Request", "Unknown archive format");
+    };
+    let mut walk = search::Walk::new(&fs, dir_path, &confine, usize::MAX, app.visibility.rules(&fs, &app.root, tail));
+    if let Some(paths) = selection {
+        walk = walk.only(paths);
+    }
+    let name = format!("{}.{}", download::archive_name(&app.root, tail), format.extension());
+    match download::ArchiveStream::new(fs, walk, format) {
+        Ok(stream) => Response::ok()
+            .header("Content-Type", format.content_type())
+            .header("Content-Disposition", download::attachment(&name))
+            .stream(Box::new(stream), None),
+        Err(_) => error_response(app, "500 Internal Server Error", "Error creating archive"),
+    }
+}
+
+/// The paths picked with "Download selected", relative to the directory
+/// `tail` the form was posted to. Each is checked as if it had been
+/// requested on its own: hidden entries are not found, and entries on disk
+/// go through the confinement checks of [`parse_requested_path`]. Inside
+/// an archive they only have to be plain relative paths.
+fn selected_paths(app: &App, fs: &dyn FileSystem, tail: &str, form: &Query, on_disk: bool) -> Result<Vec<PathBuf>, Response> {
+    let mut paths = Vec::new();
+    for selected in form.get_all("path") {
+        let relative = Path::new(selected);
+        if selected.is_empty() || relative.components().any(|c| !matches!(c, Component::Normal(_))) {
+            return Err(error_response(app, "400 Bad Request", "Bad Request"));
+        }
+        let member = match tail.trim_end_matches('/') {
+            "" => selected.to_string(),
+            dir => format!("{}/{}", dir, selected),
+        };
+        if app.visibility.hides(fs, &app.root, &member) {
+            return Err(error_response(app, "404 Not Found", "Not Found"));
+        }
+        if on_disk {
+            match resolve_path(&app.root, &member, app.symlinks) {
+                Ok(resolved) if hides_target(app, fs, &resolved) => {
+                    return Err(error_response(app, "404 Not Found", "Not Found"));
+                }
+                Ok(_) => {}
+                Err(refusal) => return Err(error_response(app, refusal.status(), &refusal.message(hidden_root(app)))),
+            }
+        }
+        paths.push(relative.to_path_buf());
+    }
+    if paths.is_empty() {
+        return Err(error_response(app, "400 Bad Request", "Nothing was selected"));
+    }
+    Ok(paths)
+}
+
+/// In static site mode, redirects a directory to a URL with plain slashes
+/// and a trailing one, and then serves its index file in place of the HTML
+/// listing. None when the directory is handled as usual.
+fn serve_site_directory(app: &App, fs: &dyn FileSystem, dir_path: &Path, tail: &str, query: &Query, info: &RequestInfo) -> Option<Response> {
+    let site = app.site.as_ref()?;
+    // Slashes encoded as %2F, as listings write them, would break the
+    // site's relative links; the rest of the encoding is left as it came.
+    let path = info.path.replace("%2F", "/").replace("%2f", "/");
+    let url = canonical_path(&path, true).unwrap_or(path);
+    if info.path != url {
+        return Some(redirect(&url, &info.query));
+    }
+    if info.format != Some(ListingFormat::Html) || query.contains("search") || query.contains("find") {
+        return None;
+    }
+    let mut index = site.index_file(fs, dir_path, &app.visibility.rules(fs, &app.root, tail))?;
+    // An index file that is a symlink is checked as if it had been requested
+    // by name, so it cannot lead out of the root either.
+    if fs.read_link(&index).is_ok() {
+        let name = index.file_name()?.to_string_lossy();
+        match resolve_path(&app.root, &Path::new(tail).join(name.as_ref()).to_string_lossy(), app.symlinks) {
+            Ok(path) => index = path,
+            Err(refusal) => return Some(error_response(app, refusal.status(), &refusal.message(hidden_root(app)))),
+        }
+    }
+    Some(generate_file_response(app, fs, &index, info.range.as_deref()))
+}
+
+fn serve_path(app: &App, fs: &dyn FileSystem, path: &Path, tail: &str, query: &Query, info: &RequestInfo) -> Response {
+    let metadata = fs.metadata(path);
+    let is_dir = metadata.as_ref().is_ok_and(|metadata| metadata.is_dir());
+    if is_dir && let Some(response) = serve_site_directory(app, fs, path, tail, query, info) {
+        return response;
+    }
+    if metadata.is_ok()
+        && let Some(location) = canonical_path(&info.path, is_dir)
+    {
+        return redirect(&location, &info.query);
+    }
+    match metadata {
+        Ok(metadata) if metadata.is_file() && query.contains("view") => generate_preview_response(app, fs, path, tail),
+        Ok(metadata) if metadata.is_file() => generate_file_response(app, fs, path, info.range.as_deref()),
+        Ok(metadata) if metadata.is_dir() && query.contains("search") => {
+            generate_search_response(app, fs, path, tail, query)
+        }
+        Ok(metadata) if metadata.is_dir() && query.contains("find") => generate_find_response(app, tail, query),
+        Ok(metadata) if metadata.is_dir() => match info.format {
+            Some(ListingFormat::Html) => generate_directory_response(app, fs, path, tail, query),
+            Some(ListingFormat::Json) => generate_json_listing(app, fs, path, tail, query),
+            Some(format @ (ListingFormat::Txt | ListingFormat::Csv)) => {
+                generate_text_listing(app, fs, path, tail, query, format, &info.origin)
+            }
+            Some(ListingFormat::Autoindex) => generate_autoindex_listing(app, fs, path, tail, query),
+            Some(ListingFormat::Atom | ListingFormat::Rss) => generate_feed(app, fs, path, tail, query, info),
+            Some(ListingFormat::M3u) => generate_playlist(app, fs, path, tail, query, &info.origin),
+            None => error_response(app, "400 Bad Request", "Unknown listing format"),
+        },
+        _ => error_response(app, "400 Bad Request", "Bad Request"),
+    }
+}
+
+/// Serves the single-page application's entry file for a path that does
+/// not exist, leaving the routing to the application.
+fn serve_spa_fallback(app: &App, fallback: &Path, info: &RequestInfo) -> Response {
+    match resolve_path(&app.root, &fallback.to_string_lossy(), app.symlinks) {
+        Ok(path) => generate_file_response(app, &LocalFs, &path, info.range.as_deref()),
+        Err(refusal) => error_response(app, refusal.status(), &refusal.message(hidden_root(app))),
+    }
+}
+
+fn handle_request(app: &App, request: &str) -> Response {
+    // A 'static borrow lets archive views be moved into streamed bodies.
+    let fs: &'static LocalFs = &LocalFs;
+    let query = request_query(request);
+    let info = RequestInfo {
+        format: ListingFormat::negotiate(&query, request_header(request, "Accept"), app.listing_format),
+        origin: request_origin(request),
+        range: request_header(request, "Range").map(str::to_string),
+        path: request_target(request).0.to_string(),
+        query: request_target(request).1.to_string(),
+    };
+    // The "Download selected" form is the only thing posted.
+    let form = (request_method(request) == "POST").then(|| Query::parse(request_body(request)));
+    if let Some(tail) = request_tail(request) {
+        // Hidden entries are answered as if they did not exist at all.
+        if app.visibility.hides(fs, &app.root, &tail) {
+            return error_response(app, "404 Not Found", "Not Found");
+        }
+        if let Some((archive_path, member)) = split_archive_path(fs, &app.root, &tail, app.symlinks) {
+            return match ArchiveFs::open(fs, &archive_path) {
+                // Ignore files inside the archive hide its members as well.
+                Ok(archive_fs) if app.visibility.hides(&archive_fs, &app.root, &tail) || hides_target(app, fs, &archive_path) => {
+                    error_response(app, "404 Not Found", "Not Found")
+                }
+                Ok(archive_fs) if let Some(form) = &form => match selected_paths(app, &archive_fs, &tail, form, false) {
+                    Ok(paths) => {
+                        let dir_path = archive_path.join(member);
+                        generate_archive_response(app, archive_fs, &dir_path, &tail, form, &info, Some(paths))
+                    }
+                    Err(response) => response,
+                },
+                Ok(archive_fs) if query.contains("grep") => {
+                    generate_grep_response(app, archive_fs, &archive_path.join(member), &tail, &query, &info)
+                }
+                Ok(archive_fs) if query.contains("archive") => {
+                    generate_archive_response(app, archive_fs, &archive_path.join(member), &tail, &query, &info, None)
+                }
+                Ok(archive_fs) => serve_path(app, &archive_fs, &archive_path.join(member), &tail, &query, &info),
+                Err(_) => error_response(app, "500 Internal Server Error", "Error reading archive"),
+            };
+        }
+        return match parse_requested_path(&app.root, request, app.symlinks) {
+            Ok(current_path) if hides_target(app, fs, &current_path) => error_response(app, "404 Not Found", "Not Found"),
+            Ok(current_path) if let Some(form) = &form => match selected_paths(app, fs, &tail, form, true) {
+                Ok(paths) => generate_archive_response(app, LocalFs, &current_path, &tail, form, &info, Some(paths)),
+                Err(response) => response,
+            },
+            Ok(current_path) if query.contains("grep") => {
+                generate_grep_response(app, LocalFs, &current_path, &tail, &query, &info)
+            }
+            Ok(current_path) if query.contains("archive") => {
+                generate_archive_response(app, LocalFs, &current_path, &tail, &query, &info, None)
+            }
+            Ok(current_path) => serve_path(app, fs, &current_path, &tail, &query, &info),
+            Err(Refusal::Missing) if let Some(fallback) = app.site.as_ref().and_then(|site| site.fallback.as_ref()) => {
+                serve_spa_fallback(app, fallback, &info)
+            }
+            Err(refusal) => error_response(app, refusal.status(), &refusal.message(hidden_root(app))),
+        };
+    }
+    error_response(app, "400 Bad Request", "Bad Request")
+}
+
+/// The most a request may take up, headers and body together. A
+/// "Download selected" form with thousands of paths fits well within it.
+const REQUEST_LIMIT: usize = 1024 * 1024;
+
+/// Reads a request's headers and, going by its `Content-Length`, its body.
+/// Stops early at [`REQUEST_LIMIT`] or when the client closes the connection.
+fn read_request(stream: &mut impl Read) -> std::io::Result<Vec<u8>> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 4096];
+    loop {
+        if let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+            let body_len = content_length(&String::from_utf8_lossy(&request[..end]));
+            if request.len() >= (end + 4).saturating_add(body_len) {
+                return Ok(request);
+            }
+        }
+        if request.len() >= REQUEST_LIMIT {
+            return Ok(request);
+        }
+        match stream.read(&mut buffer)? {
+            0 => return Ok(request),
+            len => request.extend_from_slice(&buffer[..len]),
+        }
+    }
+}
+
+fn content_length(request: &str) -> usize {
+    request_header(request, "Content-Length").and_then(|len| len.parse().ok()).unwrap_or(0)
+}
+
+fn handle_connection(app: &App, mut stream: TcpStream) {
+    let request = match read_request(&mut stream) {
+        Ok(request) if !request.is_empty() => request,
+        _ => return,
+    };
+    let request = String::from_utf8_lossy(&request);
+    let response = if content_length(&request) > REQUEST_LIMIT {
+        error_response(app, "413 Content Too Large", "Request too large")
+    } else {
+        handle_request(app, &request)
+    };
+    let _ = response.write_to(&mut stream);
+}
+
+/// Accepts connections on `listener` forever, answering each one on its own
+/// thread.
+pub fn serve(app: Arc<App>, listener: TcpListener) -> std::io::Result<()> {
+    for stream in listener.incoming().flatten() {
+        let app = Arc::clone(&app);
+        std::thread::spawn(move || handle_connection(&app, stream));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use vfs::MemoryFs;
+
+    fn test_app() -> App {
+        App::new(Config::default()).unwrap()
+    }
+
+    #[test]
+    fn test_url_decode_basic() {
+        assert_eq!(url_decode("hello%20world"), "hello world");
+        assert_eq!(url_decode("test%22quotes%22"), "test\"quotes\"");
+        assert_eq!(url_decode("no%20encoding"), "no encoding");
+    }
+
+    #[test]
+    fn test_url_decode_special_chars() {
+        assert_eq!(url_decode("path%2Fwith%2Fslashes"), "path/with/slashes");
+        assert_eq!(url_decode("hash%23tag"), "hash#tag");
+        assert_eq!(url_decode("percent%25sign"), "percent%sign");
+        assert_eq!(url_decode("ampersand%26symbol"), "ampersand&symbol");
+    }
+
+    #[test]
+    fn test_url_decode_invalid_encoding() {
+        // Invalid hex sequences should be preserved as characters
+        assert_eq!(url_decode("test%GG"), "test%GG");
+        // Incomplete sequence gets padded with '0'
+        assert_eq!(url_decode("test%1"), "test\u{10}"); // %10 -> character 16
+    }
+
+    #[test]
+    fn test_url_decode_no_encoding() {
+        assert_eq!(url_decode("plain_text"), "plain_text");
+        assert_eq!(url_decode(""), "");
+    }
+
+    #[test]
+    fn test_url_encode_basic() {
+        assert_eq!(url_encode("hello world"), "hello%20world");
+        assert_eq!(url_encode("test\"quotes\""), "test%22quotes%22");
+    }
+
+    #[test]
+    fn test_url_encode_special_chars() {
+        assert_eq!(url_encode("path/with/slashes"), "path%2Fwith%2Fslashes");
+        assert_eq!(url_encode("hash#tag"), "hash%23tag");
+        assert_eq!(url_encode("percent%sign"), "percent%25sign");
+        assert_eq!(url_encode("ampersand&symbol"), "ampersand%26symbol");
+        assert_eq!(url_encode("plus+sign"), "plus%2Bsign");
+        assert_eq!(url_encode("question?mark"), "question%3Fmark");
+    }
+
+    #[test]
+    fn test_url_encode_safe_chars() {
+        assert_eq!(url_encode("safe-chars_123.~"), "safe-chars_123.~");
+        assert_eq!(url_encode("AlphaNumeric123"), "AlphaNumeric123");
+    }
+
+    #[test]
+    fn test_url_encoding_is_utf8() {
+        assert_eq!(url_encode("日本"), "%E6%97%A5%E6%9C%AC");
+        assert_eq!(url_encode("café"), "caf%C3%A9");
+        assert_eq!(url_decode("%E6%97%A5%E6%9C%AC/caf%C3%A9"), "日本/café");
+        assert_eq!(url_decode("caf%E9"), "caf\u{FFFD}");
+    }
+
+    #[test]
+    fn test_url_encode_empty() {
+        assert_eq!(url_encode(""), "");
+    }
+
+    #[test]
+    fn test_get_mime_type_images() {
+        assert_eq!(get_mime_type(Path::new("test.jpg")), "image/jpeg");
+        assert_eq!(get_mime_type(Path::new("test.jpeg")), "image/jpeg");
+        assert_eq!(get_mime_type(Path::new("test.png")), "image/png");
+        assert_eq!(get_mime_type(Path::new("test.gif")), "image/gif");
+        assert_eq!(get_mime_type(Path::new("test.bmp")), "image/bmp");
+        assert_eq!(get_mime_type(Path::new("test.webp")), "image/webp");
+        assert_eq!(get_mime_type(Path::new("test.svg")), "image/svg+xml");
+        assert_eq!(get_mime_type(Path::new("test.ico")), "image/x-icon");
+        assert_eq!(get_mime_type(Path::new("test.tiff")), "image/tiff");
+        assert_eq!(get_mime_type(Path::new("test.tif")), "image/tiff");
+    }
+
+    #[test]
+    fn test_get_mime_type_videos() {
+        assert_eq!(get_mime_type(Path::new("test.mp4")), "video/mp4");
+        assert_eq!(get_mime_type(Path::new("test.webm")), "video/webm");
+        assert_eq!(get_mime_type(Path::new("test.ogg")), "video/ogg");
+        assert_eq!(get_mime_type(Path::new("test.mov")), "video/quicktime");
+        assert_eq!(get_mime_type(Path::new("test.avi")), "video/x-msvideo");
+        assert_eq!(get_mime_type(Path::new("test.mkv")), "video/x-matroska");
+        assert_eq!(get_mime_type(Path::new("test.wmv")), "video/x-ms-wmv");
+        assert_eq!(get_mime_type(Path::new("test.flv")), "video/x-flv");
+        assert_eq!(get_mime_type(Path::new("test.m4v")), "video/x-m4v");
+    }
+
+    #[test]
+    fn test_get_mime_type_case_insensitive() {
+        assert_eq!(get_mime_type(Path::new("test.JPG")), "image/jpeg");
+        assert_eq!(get_mime_type(Path::new("test.MP4")), "video/mp4");
+        assert_eq!(get_mime_type(Path::new("test.PnG")), "image/png");
+    }
+
+    #[test]
+    fn test_get_mime_type_unknown() {
+        assert_eq!(get_mime_type(Path::new("test.txt")), "text/plain");
+        assert_eq!(get_mime_type(Path::new("test.unknown")), "text/plain");
+        assert_eq!(get_mime_type(Path::new("test")), "text/plain");
+    }
+
+    #[test]
+    fn test_is_image_file() {
+        assert!(is_image_file(Path::new("test.jpg")));
+        assert!(is_image_file(Path::new("test.jpeg")));
+        assert!(is_image_file(Path::new("test.png")));
+        assert!(is_image_file(Path::new("test.gif")));
+        assert!(is_image_file(Path::new("test.bmp")));
+        assert!(is_image_file(Path::new("test.webp")));
+        assert!(is_image_file(Path::new("test.svg")));
+        assert!(is_image_file(Path::new("test.ico")));
+        assert!(is_image_file(Path::new("test.tiff")));
+        assert!(is_image_file(Path::new("test.tif")));
+        
+        // Case insensitive
+        assert!(is_image_file(Path::new("test.JPG")));
+        assert!(is_image_file(Path::new("test.PNG")));
+    }
+
+    #[test]
+    fn test_is_not_image_file() {
+        assert!(!is_image_file(Path::new("test.mp4")));
+        assert!(!is_image_file(Path::new("test.txt")));
+        assert!(!is_image_file(Path::new("test.unknown")));
+        assert!(!is_image_file(Path::new("test")));
+    }
+
+    #[test]
+    fn test_is_video_file() {
+        assert!(is_video_file(Path::new("test.mp4")));
+        assert!(is_video_file(Path::new("test.webm")));
+        assert!(is_video_file(Path::new("test.ogg")));
+        assert!(is_video_file(Path::new("test.mov")));
+        assert!(is_video_file(Path::new("test.avi")));
+        assert!(is_video_file(Path::new("test.mkv")));
+        assert!(is_video_file(Path::new("test.wmv")));
+        assert!(is_video_file(Path::new("test.flv")));
+        assert!(is_video_file(Path::new("test.m4v")));
+        
+        // Case insensitive
+        assert!(is_video_file(Path::new("test.MP4")));
+        assert!(is_video_file(Path::new("test.AVI")));
+    }
+
+    #[test]
+    fn test_is_audio_file() {
+        assert!(is_audio_file(Path::new("episode.mp3")));
+        assert!(is_audio_file(Path::new("episode.M4A")));
+        assert!(is_audio_file(Path::new("episode.flac")));
+        assert!(!is_audio_file(Path::new("episode.mp4")));
+        assert!(!is_audio_file(Path::new("episode")));
+        assert_eq!(get_mime_type(Path::new("episode.mp3")), "audio/mpeg");
+        assert_eq!(get_mime_type(Path::new("episode.opus")), "audio/ogg");
+    }
+
+    #[test]
+    fn test_is_not_video_file() {
+        assert!(!is_video_file(Path::new("test.jpg")));
+        assert!(!is_video_file(Path::new("test.txt")));
+        assert!(!is_video_file(Path::new("test.unknown")));
+        assert!(!is_video_file(Path::new("test")));
+    }
+
+    #[test]
+    fn test_parse_requested_path_root() {
+        let request = "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let result = parse_requested_path(&std::env::current_dir().unwrap(), request, SymlinkPolicy::Inside);
+        assert!(result.is_ok());
+        // Should return current directory for root path
+        let expected = std::env::current_dir().unwrap();
+        assert_eq!(result.unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_requested_path_invalid_request() {
+        let request = "INVALID REQUEST";
+        let result = parse_requested_path(&std::env::current_dir().unwrap(), request, SymlinkPolicy::Inside);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_requested_path_malformed() {
+        let request = "GET";
+        let result = parse_requested_path(&std::env::current_dir().unwrap(), request, SymlinkPolicy::Inside);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_requested_path_empty() {
+        let request = "";
+        let result = parse_requested_path(&std::env::current_dir().unwrap(), request, SymlinkPolicy::Inside);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_requested_path_with_encoding() {
+        let request = "GET /tests%2Fsample.jpg HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let result = parse_requested_path(&std::env::current_dir().unwrap(), request, SymlinkPolicy::Inside);
+        // This should work if the tests/sample.jpg file exists
+        if let Ok(path) = result {
+            assert!(path.to_string_lossy().contains("sample.jpg"));
+        }
+    }
+
+    #[test]
+    fn test_parse_requested_path_prevents_directory_traversal() {
+        let request = "GET /../etc/passwd HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let result = parse_requested_path(&std::env::current_dir().unwrap(), request, SymlinkPolicy::Inside);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_requested_path_prevents_absolute_paths() {
+        let request = "GET /C:/Windows/System32 HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let result = parse_requested_path(&std::env::current_dir().unwrap(), request, SymlinkPolicy::Inside);
+        // Should not allow absolute paths outside the current directory
+        if let Ok(path) = result {
+            let current_dir = std::env::current_dir().unwrap();
+            assert!(path.starts_with(&current_dir));
+        }
+    }
+
+    #[test]
+    fn test_url_encode_decode_roundtrip() {
+        let original = "hello world!@#$%^&*()";
+        let encoded = url_encode(original);
+        let decoded = url_decode(&encoded);
+        assert_eq!(decoded, original);
+    }
+
+    #[test]
+    fn test_url_encode_decode_with_spaces() {
+        let original = "file with spaces.txt";
+        let encoded = url_encode(original);
+        assert_eq!(encoded, "file%20with%20spaces.txt");
+        let decoded = url_decode(&encoded);
+        assert_eq!(decoded, original);
+    }
+
+    #[test]
+    fn test_handle_request_malformed() {
+        let request = "INVALID REQUEST FORMAT";
+        let response = handle_request(&test_app(), request).into_bytes();
+        let response_str = String::from_utf8_lossy(&response);
+        assert!(response_str.contains("400 Bad Request"));
+    }
+
+    #[test]
+    fn test_generate_file_response_from_memory() {
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/notes.txt", "hello");
+        fs.add_file("/srv/pixel.gif", vec![0x47, 0x49, 0x46, 0xff]);
+
+        let response = generate_file_response(&test_app(), &fs, Path::new("/srv/notes.txt"), None).into_bytes();
+        let response_str = String::from_utf8_lossy(&response);
+        assert!(response_str.starts_with("HTTP/1.1 200 OK"));
+        assert!(response_str.ends_with("\r\n\r\nhello"));
+
+        let response = generate_file_response(&test_app(), &fs, Path::new("/srv/pixel.gif"), None).into_bytes();
+        assert!(String::from_utf8_lossy(&response).contains("Content-Length: 4\r\n"));
+        assert!(response.ends_with(&[0x47, 0x49, 0x46, 0xff]));
+    }
+
+    #[test]
+    fn test_generate_playlist() {
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/season 1/e10.mkv", "a");
+        fs.add_file("/srv/season 1/e2.mkv", "b");
+        fs.add_file("/srv/season 1/notes.txt", "c");
+        fs.add_file("/srv/season 1/extras/blooper.mp3", "d");
+        let app = App { root: PathBuf::from("/srv"), ..test_app() };
+        let playlist = |raw_query: &str| {
+            let query = Query::parse(raw_query);
+            let response = generate_playlist(&app, &fs, Path::new("/srv/season 1"), "season 1", &query, "http://host");
+            String::from_utf8(response.into_bytes()).unwrap()
+        };
+
+        let shallow = playlist("format=m3u8");
+        assert!(shallow.contains("Content-Type: audio/x-mpegurl; charset=utf-8\r\n"));
+        assert!(shallow.contains("filename=\"season 1.m3u8\"; filename*=UTF-8''season%201.m3u8"));
+        assert!(shallow.ends_with(
+            "\r\n\r\n#EXTM3U\n#PLAYLIST:/season 1\n\
+             #EXTINF:-1,e2.mkv\nhttp://host/season%201%2Fe2.mkv\n\
+             #EXTINF:-1,e10.mkv\nhttp://host/season%201%2Fe10.mkv\n"
+        ));
+        assert!(playlist("format=m3u8&recursive").contains("http://host/season%201%2Fextras%2Fblooper.mp3\n"));
+        let reversed = playlist("format=m3u8&sort=name&order=desc");
+        assert!(reversed.find("e10.mkv").unwrap() < reversed.find("e2.mkv").unwrap());
+        assert!(!playlist("format=m3u8&q=10").contains("e2.mkv"));
+
+        let limited = |limits| {
+            let app = App { root: PathBuf::from("/srv"), search: limits, ..test_app() };
+            let response = generate_playlist(&app, &fs, Path::new("/srv/season 1"), "season 1", &Query::parse("recursive"), "");
+            String::from_utf8(response.into_bytes()).unwrap().matches("#EXTINF").count()
+        };
+        assert_eq!(limited(search::Limits { max_results: 2, ..app.search }), 2);
+        assert_eq!(limited(search::Limits { timeout: std::time::Duration::ZERO, ..app.search }), 0);
+
+        let listing = generate_directory_response(&app, &fs, Path::new("/srv/season 1"), "season 1", &Query::parse("q=e"));
+        let html = String::from_utf8(listing.into_bytes()).unwrap();
+        assert!(html.contains("<a href=\"?sort=name&order=asc&q=e&format=m3u8\">&#9654; Play all</a>"));
+    }
+
+    #[test]
+    fn test_generate_file_response_ranges() {
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/clip.mp4", "0123456789");
+        let file = |range| String::from_utf8(generate_file_response(&test_app(), &fs, Path::new("/srv/clip.mp4"), range).into_bytes()).unwrap();
+
+        let whole = file(None);
+        assert!(whole.contains("Accept-Ranges: bytes\r\n"));
+        assert!(whole.ends_with("\r\n\r\n0123456789"));
+
+        let part = file(Some("bytes=2-5"));
+        assert!(part.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+        assert!(part.contains("Content-Range: bytes 2-5/10\r\n"));
+        assert!(part.contains("Content-Length: 4\r\n"));
+        assert!(part.ends_with("\r\n\r\n2345"));
+
+        assert!(file(Some("bytes=-3")).ends_with("\r\n\r\n789"));
+        let beyond = file(Some("bytes=10-"));
+        assert!(beyond.starts_with("HTTP/1.1 416 Range Not Satisfiable\r\n"));
+        assert!(beyond.contains("Content-Range: bytes */10\r\n"));
+    }
+
+    #[test]
+    fn test_generate_file_response_missing_file() {
+        let fs = MemoryFs::new();
+        let response = generate_file_response(&test_app(), &fs, Path::new("/srv/missing.txt"), None).into_bytes();
+        assert!(String::from_utf8_lossy(&response).contains("500 Internal Server Error"));
+    }
+
+    #[test]
+    fn test_generate_directory_response_from_memory() {
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/docs/directory with spaces/file.txt", "");
+        fs.add_file("/srv/docs/readme.txt", "");
+
+        let response = generate_directory_response(&test_app(), &fs, Path::new("/srv/docs"), "docs", &Query::default()).into_bytes();
+        let response_str = String::from_utf8_lossy(&response);
+        assert!(response_str.contains("<a href=\"/docs%2Fdirectory%20with%20spaces/\">directory with spaces</a>"));
+        assert!(response_str.contains("<a href=\"/docs%2Freadme.txt\" download>Download</a>"));
+        assert!(response_str.contains("Parent Directory"));
+    }
+
+    #[test]
+    fn test_generate_directory_response_breadcrumbs() {
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/docs/a & b/notes.txt", "");
+        let app = App { root: PathBuf::from("/srv"), ..test_app() };
+        let listing = |app: &App| {
+            let response = generate_directory_response(app, &fs, Path::new("/srv/docs/a & b"), "docs/a & b", &Query::default());
+            String::from_utf8(response.into_bytes()).unwrap()
+        };
+
+        let html = listing(&app);
+        assert!(html.contains(
+            "<h1 class=\"breadcrumbs\"><a href=\"/\">/</a><a href=\"/docs/\">docs</a>/<a href=\"/docs%2Fa%20%26%20b/\" aria-current=\"page\">a &amp; b</a>/</h1>"
+        ));
+        assert!(html.contains("<title>Index of /docs/a &amp; b/</title>"));
+        assert!(html.contains("<p class=\"location\">&#x2f;srv&#x2f;docs&#x2f;a &amp; b</p>"));
+
+        let html = listing(&App { hide_path: true, ..app });
+        assert!(!html.contains("class=\"location\"") && !html.contains("srv"));
+    }
+
+    #[test]
+    fn test_generate_directory_response_details_columns() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(2048);
+        header.set_mode(0o640);
+        header.set_mtime(951_827_696);
+        header.set_cksum();
+        builder.append_data(&mut header, "report.pdf", &[0u8; 2048][..]).unwrap();
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/build.tar", builder.into_inner().unwrap());
+        let archive_fs = ArchiveFs::open(&fs, Path::new("/srv/build.tar")).unwrap();
+
+        let response = generate_directory_response(&test_app(), &archive_fs, Path::new("/srv/build.tar"), "build.tar/", &Query::default());
+        let response_str = String::from_utf8(response.into_bytes()).unwrap();
+        assert!(response_str.contains("<th><a href=\"?sort=size&order=asc\">Size</a></th>"));
+        assert!(response_str.contains("<th><a href=\"?sort=mtime&order=asc\">Modified</a></th>"));
+        assert!(response_str.contains("<th><a href=\"?sort=type&order=asc\">Type</a></th><th>Permissions</th>"));
+        assert!(response_str.contains("<td class=\"size\" title=\"2048 bytes\">2.0 KiB</td>"));
+        assert!(response_str.contains(
+            "<time datetime=\"2000-02-29T12:34:56Z\" title=\"2000-02-29 12:34:56 UTC\">"
+        ));
+        assert!(response_str.contains("years ago</time>"));
+        assert!(response_str.contains("<td class=\"type\">text&#x2f;plain</td>"));
+        assert!(response_str.contains("<td class=\"permissions\">-rw-r-----</td>"));
+    }
+
+    #[test]
+    fn test_generate_directory_response_sorted_by_query() {
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/file10.txt", "0123456789");
+        fs.add_file("/srv/file2.txt", "01");
+        fs.add_dir("/srv/zdir");
+        let listing = |raw_query: &str| {
+            let response = generate_directory_response(&test_app(), &fs, Path::new("/srv"), "", &Query::parse(raw_query));
+            let html = String::from_utf8(response.into_bytes()).unwrap();
+            let positions = ["zdir", "file2.txt", "file10.txt"].map(|name| html.find(&format!("{}</", name)).unwrap());
+            (html, positions)
+        };
+
+        let (html, [zdir, file2, file10]) = listing("");
+        assert!(zdir < file2 && file2 < file10);
+        assert!(html.contains("<th><a href=\"?sort=name&order=desc\">Name</a> &#9650;</th>"));
+
+        let (html, [zdir, file2, file10]) = listing("sort=size&order=desc&dirs=mixed");
+        assert!(file10 < file2 && file2 < zdir);
+        assert!(html.contains("<th><a href=\"?sort=size&order=asc&dirs=mixed\">Size</a> &#9660;</th>"));
+        assert!(html.contains("<a href=\"?sort=size&order=desc\">List directories first</a>"));
+    }
+
+    #[test]
+    fn test_generate_directory_response_filtered_by_query() {
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/build.log", "");
+        fs.add_file("/srv/error.log", "");
+        fs.add_file("/srv/notes.txt", "");
+        let listing = |raw_query: &str| {
+            let response = generate_directory_response(&test_app(), &fs, Path::new("/srv"), "", &Query::parse(raw_query));
+            String::from_utf8(response.into_bytes()).unwrap()
+        };
+
+        let html = listing("");
+        assert!(html.contains("3 entries"));
+
+        let html = listing("filter=*.log");
+        assert!(html.contains("build.log") && html.contains("error.log") && !html.contains("notes.txt"));
+        assert!(html.contains("2 of 3 entries"));
+        assert!(html.contains("<input type=\"search\" name=\"filter\" value=\"*.log\""));
+        assert!(html.contains("<a href=\"?sort=size&order=asc&filter=%2A.log\">Size</a>"));
+
+        let html = listing("filter=*.log&q=ERR");
+        assert!(html.contains("error.log") && !html.contains("build.log"));
+        assert!(html.contains("1 of 3 entries"));
+
+        assert!(listing("filter=%5Bunclosed").starts_with("HTTP/1.1 400 Bad Request"));
+    }
+
+    #[test]
+    fn test_generate_directory_response_paged() {
+        let fs = MemoryFs::new();
+        for i in 1..=5 {
+            fs.add_file(format!("/srv/file{}.txt", i), "");
+        }
+        let app = App { page_size: 2, ..test_app() };
+        let listing = |raw_query: &str| {
+            let response = generate_directory_response(&app, &fs, Path::new("/srv"), "", &Query::parse(raw_query));
+            String::from_utf8(response.into_bytes()).unwrap()
+        };
+
+        let html = listing("");
+        assert!(html.contains("Transfer-Encoding: chunked"));
+        assert!(html.contains("file1.txt<") && html.contains("file2.txt<") && !html.contains("file3.txt<"));
+        assert!(html.contains("5 entries"));
+        assert!(html.contains("1&ndash;2 of 5"));
+        assert!(!html.contains("rel=\"prev\""));
+        assert!(html.contains("<a href=\"?sort=name&order=asc&offset=2\" rel=\"next\">"));
+        assert!(html.contains("<a href=\"?sort=name&order=asc&limit=100\">100</a>"));
+        assert!(html.contains("<strong>2</strong>"));
+
+        let html = listing("offset=4&filter=file*");
+        assert!(html.contains("file5.txt<") && !html.contains("file4.txt<"));
+        assert!(html.contains("5&ndash;5 of 5"));
+        assert!(html.contains("<a href=\"?sort=name&order=asc&filter=file%2A&offset=2\" rel=\"prev\">"));
+        assert!(!html.contains("rel=\"next\""));
+
+        let html = listing("limit=10");
+        assert!(html.contains("1&ndash;5 of 5") && !html.contains("rel=\"next\""));
+    }
+
+    #[test]
+    fn test_generate_directory_response_in_directory_order() {
+        let fs = MemoryFs::new();
+        for i in 1..=3 {
+            fs.add_file(format!("/srv/file{}.txt", i), "");
+        }
+        fs.add_file("/srv/notes.md", "");
+        let app = App { page_size: 2, ..test_app() };
+        let listing = |raw_query: &str| {
+            let response = generate_directory_response(&app, &fs, Path::new("/srv"), "", &Query::parse(raw_query));
+            String::from_utf8(response.into_bytes()).unwrap()
+        };
+
+        let html = listing("sort=none");
+        assert_eq!(html.matches("</td><td>&#128196;").count(), 2);
+        assert!(!html.contains("class=\"count\""));
+        assert!(html.contains("<strong>Directory order</strong>"));
+        // Without a count, the end of the listing is only known on the last page.
+        assert!(html.contains("<p class=\"pager\">1&ndash;2 <a"));
+        assert!(html.contains("<a href=\"?sort=none&order=asc&offset=2\" rel=\"next\">"));
+
+        let html = listing("sort=none&offset=2");
+        assert_eq!(html.matches("</td><td>&#128196;").count(), 2);
+        assert!(html.contains("3&ndash;4 of 4") && !html.contains("rel=\"next\""));
+
+        let html = listing("sort=none&q=file&limit=5");
+        assert!(!html.contains("notes.md"));
+        assert!(html.contains("1&ndash;3 of 3"));
+
+        let html = listing("");
+        assert!(html.contains("<a href=\"?sort=none&order=asc\">Directory order (fastest for huge directories)</a>"));
+    }
+
+    #[test]
+    fn test_generate_search_response() {
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/logs/2024/app.log", "xyz");
+        fs.add_file("/srv/logs/notes.txt", "");
+        let search = |raw_query: &str| {
+            let app = App { root: PathBuf::from("/srv"), ..test_app() };
+            let response = generate_search_response(&app, &fs, Path::new("/srv/logs"), "logs", &Query::parse(raw_query));
+            String::from_utf8(response.into_bytes()).unwrap()
+        };
+
+        let html = search("search&filter=*.log");
+        assert!(html.contains("<h1>Search in &#x2f;logs</h1>"));
+        assert!(html.contains("&#128196; 2024&#x2f;app.log</td><td class=\"size\">3 B</td>"));
+        assert!(html.contains("<a href=\"/logs%2F2024%2Fapp.log?view\">View</a>"));
+        assert!(html.contains("1 result</p>"));
+        assert!(!html.contains("notes.txt"));
+
+        let html = search("search&filter=*.log&depth=0");
+        assert!(html.contains("0 results; folders more than 0 levels deep were not searched"));
+
+        assert!(search("search").contains("Enter part of a name"));
+    }
+
+    #[test]
+    fn test_generate_find_response() {
+        let root = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+        std::fs::create_dir(root.path().join("logs")).unwrap();
+        std::fs::write(root.path().join("logs/app.log"), "connection timeout").unwrap();
+        std::fs::write(root.path().join("timeout.txt"), "").unwrap();
+        let index = index::SearchIndex::open(root.path(), state.path(), visibility::Visibility::default(), SymlinkPolicy::Inside).unwrap();
+        let find = |app: &App, tail: &str, raw_query: &str| {
+            String::from_utf8(generate_find_response(app, tail, &Query::parse(raw_query)).into_bytes()).unwrap()
+        };
+
+        let html = find(&test_app(), "", "find=timeout");
+        assert!(html.starts_with("HTTP/1.1 404 Not Found"));
+
+        let app = App { root: root.path().to_path_buf(), index: Some(Arc::new(index)), ..test_app() };
+        assert!(find(&app, "", "find").contains("has not been brought up to date since the server started"));
+
+        app.index.as_ref().unwrap().update();
+        let html = find(&app, "", "find=timeout");
+        assert!(html.contains("Index of 2 files updated <time"));
+        assert!(html.contains("2 results, best matches first"));
+        let name_match = html.find("&#128196; timeout.txt").unwrap();
+        let content_match = html.find("&#128196; logs&#x2f;app.log").unwrap();
+        assert!(name_match < content_match);
+
+        let html = find(&app, "logs/", "find=timeout");
+        assert!(html.contains("&#128196; app.log</td>"));
+        assert!(html.contains("<a href=\"/logs%2Fapp.log?view\">View</a>"));
+        assert!(html.contains("1 result, best matches first"));
+    }
+
+    #[test]
+    fn test_request_header() {
+        let request = "GET / HTTP/1.1\r\nHost: example\r\naccept:  application/json \r\n\r\nAccept: ignored";
+        assert_eq!(request_header(request, "Accept"), Some("application/json"));
+        assert_eq!(request_header(request, "host"), Some("example"));
+        assert_eq!(request_header(request, "Range"), None);
+    }
+
+    #[test]
+    fn test_request_origin() {
+        assert_eq!(request_origin("GET / HTTP/1.1\r\nHost: files.example:8080\r\n\r\n"), "http://files.example:8080");
+        assert_eq!(request_origin("GET / HTTP/1.1\r\nHost: [::1]:8080\r\n\r\n"), "http://[::1]:8080");
+        assert_eq!(request_origin("GET / HTTP/1.1\r\nHost: evil\"><x\r\n\r\n"), "");
+        assert_eq!(request_origin("GET / HTTP/1.0\r\n\r\n"), "");
+    }
+
+    #[test]
+    fn test_generate_feed() {
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/drop/top.txt", "a");
+        fs.add_file("/srv/drop/sub/photo.png", "b");
+        fs.add_file("/srv/drop/sub/standup.mp4", "c");
+        let app = App { root: PathBuf::from("/srv"), ..test_app() };
+        let feed = |raw_query: &str| {
+            let query = Query::parse(raw_query);
+            let info = RequestInfo {
+                format: ListingFormat::negotiate(&query, None, ListingFormat::Html),
+                origin: "http://host".to_string(),
+                ..RequestInfo::default()
+            };
+            let response = generate_feed(&app, &fs, Path::new("/srv/drop"), "drop", &query, &info);
+            String::from_utf8(response.into_bytes()).unwrap()
+        };
+        let shallow = feed("format=atom");
+        assert!(shallow.contains("Content-Type: application/atom+xml; charset=utf-8"));
+        assert!(shallow.contains("href=\"http://host/drop%2Ftop.txt\""));
+        assert!(!shallow.contains("photo.png"));
+
+        let deep = feed("format=atom&recursive");
+        assert!(deep.contains("<media:thumbnail url=\"http://host/drop%2Fsub%2Fphoto.png\"/>"));
+        assert!(deep.contains("href=\"http://host/drop/?format=atom&amp;recursive\""));
+        assert_eq!(feed("format=atom&recursive&limit=1").matches("<entry>").count(), 1);
+
+        let podcast = feed("format=rss&recursive");
+        assert!(podcast.contains("Content-Type: application/rss+xml; charset=utf-8"));
+        assert_eq!(podcast.matches("<item>").count(), 1);
+        assert!(podcast.contains("<enclosure url=\"http://host/drop%2Fsub%2Fstandup.mp4\" length=\"1\" type=\"video/mp4\"/>"));
+    }
+
+    #[test]
+    fn test_generate_json_listing() {
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/docs/a & b.txt", "hello");
+        fs.add_dir("/srv/docs/sub");
+        let response = generate_json_listing(&test_app(), &fs, Path::new("/srv/docs"), "docs", &Query::parse("sort=size&order=desc"));
+        let response = String::from_utf8(response.into_bytes()).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.contains("Content-Type: application/json"));
+
+        let json: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(json["schema"], "rust-web-explorer/listing");
+        assert_eq!(json["version"], 1);
+        assert_eq!(json["path"], "/docs");
+        assert_eq!(json["url"], "/docs/");
+        assert_eq!(json["parent"], serde_json::json!({ "name": "/", "url": "/" }));
+        assert_eq!(json["breadcrumbs"][1], serde_json::json!({ "name": "docs", "url": "/docs/" }));
+        assert_eq!(json["total_entries"], 2);
+        assert_eq!(json["entries"][0]["name"], "sub");
+        assert_eq!(json["entries"][0]["kind"], "directory");
+        assert_eq!(json["entries"][0]["size"], serde_json::Value::Null);
+        let file = &json["entries"][1];
+        assert_eq!(file["kind"], "file");
+        assert_eq!(file["size"], 5);
+        assert_eq!(file["mime_type"], "text/plain");
+        assert_eq!(file["url"], "/docs%2Fa%20%26%20b.txt");
+        assert!(file["modified"].as_str().unwrap().ends_with('Z'));
+    }
+
+    #[test]
+    fn test_generate_text_listings() {
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/docs/a, b.txt", "hello");
+        fs.add_dir("/srv/docs/sub");
+        let query = Query::parse("");
+        let txt = generate_text_listing(&test_app(), &fs, Path::new("/srv/docs"), "docs", &query, ListingFormat::Txt, "http://files.local:8080");
+        let txt = String::from_utf8(txt.into_bytes()).unwrap();
+        assert!(txt.contains("Content-Type: text/plain; charset=utf-8"));
+        assert!(txt.ends_with("\r\n\r\nhttp://files.local:8080/docs%2Fsub/\nhttp://files.local:8080/docs%2Fa%2C%20b.txt\n"));
+
+        let csv = generate_text_listing(&test_app(), &fs, Path::new("/srv/docs"), "docs", &query, ListingFormat::Csv, "");
+        let csv = String::from_utf8(csv.into_bytes()).unwrap();
+        assert!(csv.contains("Content-Type: text/csv; charset=utf-8"));
+        let (_, body) = csv.split_once("\r\n\r\n").unwrap();
+        let lines = body.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "name,kind,size,modified,mime_type,url");
+        assert!(lines[1].starts_with("sub,directory,,"));
+        assert!(lines[1].ends_with(",,/docs%2Fsub/"));
+        assert!(lines[2].starts_with("\"a, b.txt\",file,5,"));
+        assert!(lines[2].ends_with(",text/plain,/docs%2Fa%2C%20b.txt"));
+    }
+
+    #[test]
+    fn test_generate_autoindex_listing() {
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/docs/a & b.txt", vec![0; 1536]);
+        fs.add_dir("/srv/docs/sub dir");
+        let response = generate_autoindex_listing(&test_app(), &fs, Path::new("/srv/docs"), "docs", &Query::parse(""));
+        let html = String::from_utf8(response.into_bytes()).unwrap();
+        assert!(html.contains("<title>Index of /docs</title>"));
+        assert!(html.contains("<a href=\"/\">Parent Directory</a>"));
+        assert!(html.contains("<a href=\"/docs/sub%20dir/\">sub dir/</a>"));
+        assert!(html.contains("<a href=\"/docs/a%20%26%20b.txt\">a &amp; b.txt</a>"));
+        assert!(html.contains(" 1.5K\n"));
+    }
+
+    #[test]
+    fn test_generate_directory_response_escapes_names() {
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/<b>bold.txt", "");
+        let response = generate_directory_response(&test_app(), &fs, Path::new("/srv"), "", &Query::default()).into_bytes();
+        let response_str = String::from_utf8_lossy(&response);
+        assert!(response_str.contains("&lt;b&gt;bold.txt"));
+        assert!(!response_str.contains("<b>bold"));
+    }
+
+    #[test]
+    fn test_generate_preview_response() {
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/docs/notes.txt", "1 < 2\n3 > 2\n");
+        fs.add_file("/srv/docs/pixel.gif", vec![0x47, 0x49, 0x46]);
+
+        let response = generate_preview_response(&test_app(), &fs, Path::new("/srv/docs/notes.txt"), "docs/notes.txt");
+        let response_str = String::from_utf8(response.into_bytes()).unwrap();
+        assert!(response_str.contains("Content-Type: text/html; charset=utf-8"));
+        assert!(response_str.contains("<pre class=\"lines\"><span id=\"L1\">1 &lt; 2</span>\n<span id=\"L2\">3 &gt; 2</span>\n</pre>"));
+        assert!(response_str.contains("<a href=\"/docs/\">Back to listing</a>"));
+
+        let response = generate_preview_response(&test_app(), &fs, Path::new("/srv/docs/pixel.gif"), "docs/pixel.gif");
+        let response_str = String::from_utf8(response.into_bytes()).unwrap();
+        assert!(response_str.contains("<img src=\"/docs%2Fpixel.gif\""));
+    }
+
+    #[test]
+    fn test_breadcrumbs() {
+        let crumbs = breadcrumbs("docs/directory with spaces/");
+        let names = crumbs.iter().map(|crumb| crumb.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["/", "docs", "directory with spaces"]);
+        assert_eq!(crumbs[2].url.to_string(), "/docs%2Fdirectory%20with%20spaces/");
+    }
+
+    #[test]
+    fn test_slash_url_and_redirect() {
+        assert_eq!(slash_url(""), "/");
+        assert_eq!(slash_url("docs/directory with spaces"), "/docs/directory%20with%20spaces/");
+        let response = String::from_utf8(redirect("/docs/", "sort=size").into_bytes()).unwrap();
+        assert!(response.starts_with("HTTP/1.1 301 Moved Permanently\r\nLocation: /docs/?sort=size\r\n"));
+        assert!(String::from_utf8(redirect("/docs/", "").into_bytes()).unwrap().contains("Location: /docs/\r\n"));
+    }
+
+    #[test]
+    fn test_generate_directory_response_missing_dir() {
+        let fs = MemoryFs::new();
+        let response = generate_directory_response(&test_app(), &fs, Path::new("/srv/missing"), "missing", &Query::default()).into_bytes();
+        assert!(String::from_utf8_lossy(&response).contains("500 Internal Server Error"));
+    }
+
+    #[test]
+    fn test_generate_directory_response_for_archive() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(3);
+        header.set_cksum();
+        builder.append_data(&mut header, "logs/build log.txt", &b"ok\n"[..]).unwrap();
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/artifacts/build.tar", builder.into_inner().unwrap());
+
+        let response = generate_directory_response(&test_app(), &fs, Path::new("/srv/artifacts"), "artifacts", &Query::default()).into_bytes();
+        assert!(String::from_utf8_lossy(&response).contains("<a href=\"/artifacts%2Fbuild.tar/\">Browse</a>"));
+
+        let archive_fs = ArchiveFs::open(&fs, Path::new("/srv/artifacts/build.tar")).unwrap();
+        let response = serve_path(&test_app(), &archive_fs, Path::new("/srv/artifacts/build.tar/logs"), "artifacts/build.tar/logs/", &Query::default(), &RequestInfo { format: Some(ListingFormat::Html), path: "/artifacts/build.tar/logs/".to_string(), ..RequestInfo::default() });
+        let response_str = String::from_utf8(response.into_bytes()).unwrap();
+        assert!(response_str.contains("<a href=\"/artifacts%2Fbuild.tar/\">Parent Directory</a>"));
+        assert!(response_str.contains("<a href=\"/artifacts%2Fbuild.tar%2Flogs%2Fbuild%20log.txt?view\">View</a>"));
+
+        let response = serve_path(&test_app(), &archive_fs, Path::new("/srv/artifacts/build.tar/logs/build log.txt"), "", &Query::default(), &RequestInfo::default());
+        let response_str = String::from_utf8(response.into_bytes()).unwrap();
+        assert!(response_str.contains("Content-Type: text/plain\r\n"));
+        assert!(response_str.ends_with("\r\n\r\nok\n"));
+
+        let info = RequestInfo { path: "/artifacts/build.tar/logs".to_string(), query: "sort=size".to_string(), ..RequestInfo::default() };
+        let response = serve_path(&test_app(), &archive_fs, Path::new("/srv/artifacts/build.tar/logs"), "artifacts/build.tar/logs", &Query::default(), &info);
+        assert!(String::from_utf8(response.into_bytes()).unwrap().contains("Location: /artifacts/build.tar/logs/?sort=size\r\n"));
+    }
+
+    #[test]
+    fn test_canonical_path() {
+        assert_eq!(canonical_path("/", true), None);
+        assert_eq!(canonical_path("/docs/", true), None);
+        assert_eq!(canonical_path("/docs", true), Some("/docs/".to_string()));
+        assert_eq!(canonical_path("/docs%2Fsub%2F", true), Some("/docs%2Fsub/".to_string()));
+        assert_eq!(canonical_path("/docs//", true), Some("/docs/".to_string()));
+        assert_eq!(canonical_path("/docs%2Fa.txt", false), None);
+        assert_eq!(canonical_path("/docs/a.txt/", false), Some("/docs/a.txt".to_string()));
+    }
+
+    #[test]
+    fn test_split_archive_path_requires_trailing_segment() {
+        let fs = MemoryFs::new();
+        assert!(split_archive_path(&fs, &std::env::current_dir().unwrap(), "tests/sample.jpg/", SymlinkPolicy::Inside).is_none());
+        assert!(split_archive_path(&fs, &std::env::current_dir().unwrap(), "missing.zip/logs", SymlinkPolicy::Inside).is_none());
+    }
+
+    #[test]
+    fn test_handle_request_empty() {
+        let request = "";
+        let response = handle_request(&test_app(), request).into_bytes();
+        let response_str = String::from_utf8_lossy(&response);
+        assert!(response_str.contains("400 Bad Request"));
+    }
+}
\ No newline at end of file
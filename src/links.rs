@@ -0,0 +1,196 @@
+use std::path::{Component, Path, PathBuf};
+
+/// How symlinks below the served root are treated, as chosen with
+/// `--symlinks`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkPolicy {
+    /// Links are followed as long as they lead somewhere inside the root.
+    #[default]
+    Inside,
+    /// Links are followed wherever they lead.
+    Anywhere,
+    /// Links are listed but never followed.
+    Never,
+}
+
+impl SymlinkPolicy {
+    pub fn parse(value: &str) -> Option<SymlinkPolicy> {
+        match value {
+            "inside" => Some(SymlinkPolicy::Inside),
+            "anywhere" => Some(SymlinkPolicy::Anywhere),
+            "never" => Some(SymlinkPolicy::Never),
+            _ => None,
+        }
+    }
+}
+
+/// Where a walk of the tree may go: the served root as the backend sees
+/// it, and how links leading away from it are treated.
+#[derive(Debug, Clone)]
+pub struct Confinement {
+    pub root: PathBuf,
+    pub policy: SymlinkPolicy,
+}
+
+impl Confinement {
+    /// Whether an entry whose real location is `real` may be stepped onto.
+    /// `is_link` is only asked when the answer depends on it.
+    pub fn admits(&self, real: &Path, is_link: impl FnOnce() -> bool) -> bool {
+        match self.policy {
+            SymlinkPolicy::Inside => real.starts_with(&self.root),
+            SymlinkPolicy::Anywhere => true,
+            SymlinkPolicy::Never => real.starts_with(&self.root) && !is_link(),
+        }
+    }
+}
+
+/// Why a requested path was not resolved.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Refusal {
+    /// The path is malformed or steps up with `..`.
+    Invalid,
+    /// The path is well-formed but nothing exists there.
+    Missing,
+    /// `link`, relative to the root, is a symlink and links are not followed.
+    NotFollowed { link: String },
+    /// `link` points out of the served root.
+    OutsideRoot { link: String, target: PathBuf },
+    /// `link` points at something that does not exist.
+    Broken { link: String, target: PathBuf },
+}
+
+impl Refusal {
+    pub fn status(&self) -> &'static str {
+        match self {
+            Refusal::Invalid | Refusal::Missing => "400 Bad Request",
+            Refusal::NotFollowed { .. } | Refusal::OutsideRoot { .. } => "403 Forbidden",
+            Refusal::Broken { .. } => "404 Not Found",
+        }
+    }
+
+    /// With `hidden_root` set, targets are shown as seen from that root, and
+    /// targets outside it are not shown at all.
+    pub fn message(&self, hidden_root: Option<&Path>) -> String {
+        let shown = |link: &str, target: &Path| match hidden_root {
+            Some(root) => target_from_root(root, Path::new(link), target),
+            None => Some(target.display().to_string()),
+        };
+        match self {
+            Refusal::Invalid | Refusal::Missing => "Bad Request".to_string(),
+            Refusal::NotFollowed { link } => {
+                format!("/{} is a symbolic link, and this server does not follow symbolic links", link)
+            }
+            Refusal::OutsideRoot { link, target } => match shown(link, target) {
+                Some(target) => format!("/{} is a symbolic link to {}, which is outside the served directory", link, target),
+                None => format!("/{} is a symbolic link to somewhere outside the served directory", link),
+            },
+            Refusal::Broken { link, target } => match shown(link, target) {
+                Some(target) => format!("/{} is a symbolic link to {}, which does not exist", link, target),
+                None => format!("/{} is a symbolic link to something outside the served directory that does not exist", link),
+            },
+        }
+    }
+}
+
+/// Where the link at `link`, relative to `root`, leads, as a path from the
+/// root such as `/docs/v2`; `None` when it leads out of the root. Used in
+/// place of the target as written when the root's own path is kept private.
+pub fn target_from_root(root: &Path, link: &Path, target: &Path) -> Option<String> {
+    let resolved = lexical_target(&root.join(link), target)?;
+    let relative = resolved.strip_prefix(root).ok()?;
+    Some(format!("/{}", relative.display()))
+}
+
+/// Where the link at `link` leads, worked out from the path alone: `..`
+/// steps back from the directory holding the link, and links along the way
+/// are not resolved.
+pub fn lexical_target(link: &Path, target: &Path) -> Option<PathBuf> {
+    let mut resolved = PathBuf::new();
+    for component in link.parent()?.join(target).components() {
+        match component {
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::CurDir => {}
+            component => resolved.push(component),
+        }
+    }
+    Some(resolved)
+}
+
+/// The path that leads from the directory holding `from` to `to`, both
+/// relative to the same directory, e.g. `../v2` from `docs/latest` to `v2`.
+pub fn relative_link(from: &Path, to: &Path) -> PathBuf {
+    let base = from.parent().unwrap_or(Path::new(""));
+    let shared = base.components().zip(to.components()).take_while(|(a, b)| a == b).count();
+    let mut path = PathBuf::new();
+    for _ in base.components().skip(shared) {
+        path.push("..");
+    }
+    path.extend(to.components().skip(shared));
+    if path.as_os_str().is_empty() { PathBuf::from(".") } else { path }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symlink_policy_parse() {
+        assert_eq!(SymlinkPolicy::parse("inside"), Some(SymlinkPolicy::Inside));
+        assert_eq!(SymlinkPolicy::parse("anywhere"), Some(SymlinkPolicy::Anywhere));
+        assert_eq!(SymlinkPolicy::parse("never"), Some(SymlinkPolicy::Never));
+        assert_eq!(SymlinkPolicy::parse("sometimes"), None);
+    }
+
+    #[test]
+    fn test_confinement_admits() {
+        let confine = |policy| Confinement { root: PathBuf::from("/srv"), policy };
+        let inside = Path::new("/srv/docs");
+        let outside = Path::new("/etc/passwd");
+        assert!(confine(SymlinkPolicy::Inside).admits(inside, || true));
+        assert!(!confine(SymlinkPolicy::Inside).admits(outside, || true));
+        assert!(confine(SymlinkPolicy::Anywhere).admits(outside, || true));
+        assert!(!confine(SymlinkPolicy::Never).admits(inside, || true));
+        assert!(confine(SymlinkPolicy::Never).admits(inside, || false));
+    }
+
+    #[test]
+    fn test_refusal_messages() {
+        let refusal = Refusal::OutsideRoot { link: "docs/etc".to_string(), target: PathBuf::from("/etc") };
+        assert_eq!(refusal.status(), "403 Forbidden");
+        assert_eq!(refusal.message(None), "/docs/etc is a symbolic link to /etc, which is outside the served directory");
+        let refusal = Refusal::Broken { link: "latest".to_string(), target: PathBuf::from("v2") };
+        assert_eq!(refusal.status(), "404 Not Found");
+        assert_eq!(refusal.message(None), "/latest is a symbolic link to v2, which does not exist");
+    }
+
+    #[test]
+    fn test_refusal_messages_keep_the_root_private() {
+        let root = Some(Path::new("/srv/files"));
+        let refusal = Refusal::OutsideRoot { link: "docs/etc".to_string(), target: PathBuf::from("/etc") };
+        assert_eq!(refusal.message(root), "/docs/etc is a symbolic link to somewhere outside the served directory");
+        let refusal = Refusal::Broken { link: "docs/latest".to_string(), target: PathBuf::from("../v2") };
+        assert_eq!(refusal.message(root), "/docs/latest is a symbolic link to /v2, which does not exist");
+        let refusal = Refusal::Broken { link: "latest".to_string(), target: PathBuf::from("../old/v2") };
+        assert!(!refusal.message(root).contains("old"));
+    }
+
+    #[test]
+    fn test_target_from_root() {
+        let root = Path::new("/srv/files");
+        assert_eq!(target_from_root(root, Path::new("docs/latest"), Path::new("v2")).as_deref(), Some("/docs/v2"));
+        assert_eq!(target_from_root(root, Path::new("docs/up"), Path::new("./../readme.txt")).as_deref(), Some("/readme.txt"));
+        assert_eq!(target_from_root(root, Path::new("abs"), Path::new("/srv/files/docs")).as_deref(), Some("/docs"));
+        assert_eq!(target_from_root(root, Path::new("docs/out"), Path::new("../../other")), None);
+        assert_eq!(target_from_root(root, Path::new("etc"), Path::new("/etc")), None);
+    }
+
+    #[test]
+    fn test_relative_link() {
+        assert_eq!(relative_link(Path::new("docs/latest"), Path::new("docs/v2")), Path::new("v2"));
+        assert_eq!(relative_link(Path::new("docs/latest"), Path::new("v2")), Path::new("../v2"));
+        assert_eq!(relative_link(Path::new("a/b/up"), Path::new("a")), Path::new(".."));
+        assert_eq!(relative_link(Path::new("top"), Path::new("")), Path::new("."));
+    }
+}
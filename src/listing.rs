@@ -0,0 +1,389 @@
+use std::io::{self, Read};
+use std::sync::Arc;
+
+use minijinja::Value;
+use serde::Serialize;
+
+use crate::http::Query;
+use crate::templates::{Entry, ListingContext, Templates, url_value};
+
+/// Identifies JSON listings, so clients can check what they are parsing.
+pub const SCHEMA: &str = "rust-web-explorer/listing";
+
+/// Bumped whenever a field of the JSON listing is removed or changes
+/// meaning. Adding fields does not change the version.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The representations a directory listing can be returned in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListingFormat {
+    Html,
+    Json,
+    /// One URL per line, e.g. for `wget -i`.
+    Txt,
+    Csv,
+    /// Markup in the style of Apache's mod_autoindex, for mirroring tools.
+    Autoindex,
+    /// A feed of the most recently modified files.
+    Atom,
+    /// A podcast feed of the audio and video files.
+    Rss,
+    /// An extended M3U playlist of the audio and video files.
+    M3u,
+}
+
+impl ListingFormat {
+    /// Picks the format from `?format=`, falling back to the Accept header
+    /// and then to `default`, the server's HTML flavour. Returns None for a
+    /// `?format=` that is not supported.
+    pub fn negotiate(query: &Query, accept: Option<&str>, default: ListingFormat) -> Option<ListingFormat> {
+        match query.get("format") {
+            Some("html") => Some(ListingFormat::Html),
+            Some("json") => Some(ListingFormat::Json),
+            Some("txt") => Some(ListingFormat::Txt),
+            Some("csv") => Some(ListingFormat::Csv),
+            Some("autoindex") => Some(ListingFormat::Autoindex),
+            Some("atom") => Some(ListingFormat::Atom),
+            Some("rss") => Some(ListingFormat::Rss),
+            Some("m3u" | "m3u8") => Some(ListingFormat::M3u),
+            Some(_) => None,
+            // Browsers list text/html; only clients asking for JSON alone get it.
+            None if accept.is_some_and(|accept| accept.contains("application/json") && !accept.contains("text/html")) => {
+                Some(ListingFormat::Json)
+            }
+            None => Some(default),
+        }
+    }
+}
+
+/// Renders the entries' URLs one per line, prefixed with `origin` (see
+/// `request_origin`), which leaves them root-relative when empty.
+pub fn to_txt(context: &ListingContext, origin: &str) -> String {
+    context.entries.iter().map(|entry| format!("{}{}\n", origin, entry.url)).collect()
+}
+
+/// Renders the entries as CSV with a header row, quoting fields as RFC 4180
+/// describes.
+pub fn to_csv(context: &ListingContext) -> String {
+    let mut csv = String::from("name,kind,size,modified,mime_type,url\r\n");
+    for entry in &context.entries {
+        let entry = JsonEntry::new(entry);
+        let fields = [
+            csv_field(entry.name),
+            entry.kind.to_string(),
+            entry.size.map(|size| size.to_string()).unwrap_or_default(),
+            entry.modified.unwrap_or_default().to_string(),
+            entry.mime_type.unwrap_or_default().to_string(),
+            csv_field(&entry.url),
+        ];
+        csv.push_str(&fields.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Formats a size the way mod_autoindex does, e.g. `512`, `1.5K` or `12M`.
+pub fn autoindex_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["K", "M", "G", "T", "P"];
+    if bytes < 1024 {
+        return bytes.to_string();
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if value < 10.0 { format!("{:.1}{}", value, UNITS[unit]) } else { format!("{:.0}{}", value, UNITS[unit]) }
+}
+
+/// The page sizes offered below a listing, besides the server's default.
+const PAGE_SIZES: [usize; 4] = [100, 500, 1000, 5000];
+
+/// A window of a listing, chosen with `?offset=` and `?limit=`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Page {
+    pub offset: usize,
+    pub limit: usize,
+}
+
+impl Page {
+    pub fn from_query(query: &Query, default_limit: usize) -> Page {
+        Page {
+            offset: query.get("offset").and_then(|offset| offset.parse().ok()).unwrap_or(0),
+            limit: query.get("limit").and_then(|limit| limit.parse().ok()).filter(|&limit| limit > 0).unwrap_or(default_limit),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PageSize {
+    pub size: usize,
+    pub url: Value,
+    pub current: bool,
+}
+
+/// Context for the `table_end` macro of `listing_rows.html`, rendered once
+/// every row of the page has been sent.
+#[derive(Debug, Serialize)]
+pub struct Pager {
+    /// Counted from 1; both are 0 when the page is empty.
+    pub first: usize,
+    pub last: usize,
+    /// How many entries passed the filter, when known. A listing in
+    /// directory order only knows once it has reached the end.
+    pub matched: Option<usize>,
+    pub prev_url: Option<Value>,
+    pub next_url: Option<Value>,
+    pub sizes: Vec<PageSize>,
+}
+
+/// A response body that sends a listing page as it is produced: the page
+/// up to its rows, each row as it comes out of `rows`, then the end of the
+/// table with links to the neighbouring pages. Only one row is held at a time.
+pub struct ListingStream {
+    rows: Box<dyn Iterator<Item = Entry> + Send>,
+    page: Page,
+    default_limit: usize,
+    shown: usize,
+    matched: Option<usize>,
+    /// The listing's query string (sort and filter), starting with '?',
+    /// which the page links extend.
+    base_query: String,
+    templates: Arc<Templates>,
+    suffix: Option<String>,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl ListingStream {
+    /// Sends at most `page.limit` of `rows`, which must already skip the
+    /// entries before `page.offset`. `matched` is the number of entries on
+    /// all pages when it is known up front.
+    pub fn new(
+        rows: Box<dyn Iterator<Item = Entry> + Send>,
+        (page, default_limit): (Page, usize),
+        matched: Option<usize>,
+        base_query: String,
+        templates: Arc<Templates>,
+        (prefix, suffix): (String, String),
+    ) -> ListingStream {
+        ListingStream {
+            rows,
+            page,
+            default_limit,
+            shown: 0,
+            matched,
+            base_query,
+            templates,
+            suffix: Some(suffix),
+            buffer: prefix.into_bytes(),
+            position: 0,
+        }
+    }
+
+    fn page_url(&self, offset: usize, limit: usize) -> Value {
+        let mut url = self.base_query.clone();
+        if offset > 0 {
+            url.push_str(&format!("&offset={}", offset));
+        }
+        if limit != self.default_limit {
+            url.push_str(&format!("&limit={}", limit));
+        }
+        url_value(url)
+    }
+
+    /// Refills the buffer with the next row, or with the end of the table
+    /// once the page is full. Returns false when nothing is left.
+    fn fill(&mut self) -> io::Result<bool> {
+        self.buffer.clear();
+        self.position = 0;
+        if self.shown < self.page.limit
+            && let Some(entry) = self.rows.next()
+        {
+            self.shown += 1;
+            let row = self.templates.call_macro("listing_rows.html", "row", Value::from_serialize(&entry));
+            self.buffer = row.map_err(io::Error::other)?.into_bytes();
+            return Ok(true);
+        }
+
+        let Some(suffix) = self.suffix.take() else { return Ok(false) };
+        let Page { offset, limit } = self.page;
+        let has_next = match self.matched {
+            Some(matched) => offset + self.shown < matched,
+            None => self.rows.next().is_some(),
+        };
+        if !has_next && self.matched.is_none() {
+            self.matched = Some(offset + self.shown);
+        }
+        let mut sizes = PAGE_SIZES.to_vec();
+        sizes.extend([self.default_limit, limit].iter().filter(|size| !PAGE_SIZES.contains(size)));
+        sizes.sort_unstable();
+        sizes.dedup();
+        let pager = Pager {
+            first: if self.shown == 0 { 0 } else { offset + 1 },
+            last: offset + self.shown,
+            matched: self.matched,
+            prev_url: (offset > 0).then(|| self.page_url(offset.saturating_sub(limit), limit)),
+            next_url: has_next.then(|| self.page_url(offset + limit, limit)),
+            sizes: sizes.into_iter().map(|size| PageSize { size, url: self.page_url(offset, size), current: size == limit }).collect(),
+        };
+        let table_end = self.templates.call_macro("listing_rows.html", "table_end", Value::from_serialize(&pager));
+        self.buffer = table_end.map_err(io::Error::other)?.into_bytes();
+        self.buffer.extend_from_slice(suffix.as_bytes());
+        Ok(true)
+    }
+}
+
+impl Read for ListingStream {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.buffer.len() {
+            if !self.fill()? {
+                return Ok(0);
+            }
+        }
+        let len = out.len().min(self.buffer.len() - self.position);
+        out[..len].copy_from_slice(&self.buffer[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonLink {
+    pub name: String,
+    pub url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonEntry<'a> {
+    pub name: &'a str,
+    /// `file` or `directory`.
+    pub kind: &'static str,
+    pub size: Option<u64>,
+    /// RFC 3339, in UTC.
+    pub modified: Option<&'a str>,
+    pub modified_unix: Option<i64>,
+    pub mime_type: Option<&'static str>,
+    pub url: String,
+    pub permissions: Option<&'a str>,
+    /// The symlink's target as written in the link, or `None` for other
+    /// entries. With `--hide-path`, see [`Entry::link_target`].
+    pub link_target: Option<&'a str>,
+    pub broken_link: bool,
+}
+
+/// A directory listing as served for `?format=json`. See the README for
+/// the meaning of each field.
+#[derive(Debug, Serialize)]
+pub struct JsonListing<'a> {
+    pub schema: &'static str,
+    pub version: u32,
+    /// The directory relative to the served root, e.g. `/docs`.
+    pub path: String,
+    pub url: String,
+    pub parent: Option<JsonLink>,
+    pub breadcrumbs: Vec<JsonLink>,
+    /// How many entries the directory holds before filtering.
+    pub total_entries: usize,
+    pub entries: Vec<JsonEntry<'a>>,
+}
+
+impl<'a> JsonListing<'a> {
+    pub fn new(context: &'a ListingContext) -> JsonListing<'a> {
+        let breadcrumbs = context
+            .breadcrumbs
+            .iter()
+            .map(|crumb| JsonLink { name: crumb.name.clone(), url: crumb.url.to_string() })
+            .collect::<Vec<_>>();
+        let path = breadcrumbs.iter().skip(1).map(|crumb| format!("/{}", crumb.name)).collect::<String>();
+        let parent = context.parent_url.as_ref().map(|url| {
+            let name = match breadcrumbs.len() {
+                0..=2 => "/".to_string(),
+                len => breadcrumbs[len - 2].name.clone(),
+            };
+            JsonLink { name, url: url.to_string() }
+        });
+        JsonListing {
+            schema: SCHEMA,
+            version: SCHEMA_VERSION,
+            path: if path.is_empty() { "/".to_string() } else { path },
+            url: breadcrumbs.last().map_or_else(|| "/".to_string(), |crumb| crumb.url.clone()),
+            parent,
+            breadcrumbs,
+            total_entries: context.total_entries,
+            entries: context.entries.iter().map(JsonEntry::new).collect(),
+        }
+    }
+}
+
+impl<'a> JsonEntry<'a> {
+    fn new(entry: &'a Entry) -> JsonEntry<'a> {
+        JsonEntry {
+            name: &entry.name,
+            kind: if entry.is_dir { "directory" } else { "file" },
+            size: entry.size,
+            modified: entry.modified_iso.as_deref(),
+            modified_unix: entry.modified,
+            mime_type: entry.mime_type,
+            url: entry.url.to_string(),
+            permissions: entry.permissions.as_deref(),
+            link_target: entry.link_target.as_deref(),
+            broken_link: entry.broken_link,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_from_query() {
+        assert_eq!(Page::from_query(&Query::default(), 500), Page { offset: 0, limit: 500 });
+        assert_eq!(Page::from_query(&Query::parse("offset=1000&limit=100"), 500), Page { offset: 1000, limit: 100 });
+        assert_eq!(Page::from_query(&Query::parse("offset=-1&limit=0"), 500), Page { offset: 0, limit: 500 });
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let negotiate =
+            |raw_query: &str, accept| ListingFormat::negotiate(&Query::parse(raw_query), accept, ListingFormat::Html);
+        assert_eq!(negotiate("", None), Some(ListingFormat::Html));
+        assert_eq!(negotiate("format=txt", None), Some(ListingFormat::Txt));
+        assert_eq!(negotiate("format=csv", None), Some(ListingFormat::Csv));
+        assert_eq!(negotiate("format=autoindex", None), Some(ListingFormat::Autoindex));
+        let autoindex = ListingFormat::negotiate(&Query::parse(""), None, ListingFormat::Autoindex);
+        assert_eq!(autoindex, Some(ListingFormat::Autoindex));
+        assert_eq!(negotiate("format=atom", None), Some(ListingFormat::Atom));
+        assert_eq!(negotiate("format=rss", None), Some(ListingFormat::Rss));
+        assert_eq!(negotiate("format=m3u8", None), Some(ListingFormat::M3u));
+        assert_eq!(negotiate("format=json", None), Some(ListingFormat::Json));
+        assert_eq!(negotiate("format=html", Some("application/json")), Some(ListingFormat::Html));
+        assert_eq!(negotiate("", Some("application/json")), Some(ListingFormat::Json));
+        assert_eq!(negotiate("", Some("text/html,application/xhtml+xml,application/json;q=0.9")), Some(ListingFormat::Html));
+        assert_eq!(negotiate("format=yaml", None), None);
+    }
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("plain.txt"), "plain.txt");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn test_autoindex_size() {
+        assert_eq!(autoindex_size(512), "512");
+        assert_eq!(autoindex_size(1536), "1.5K");
+        assert_eq!(autoindex_size(12 * 1024 * 1024), "12M");
+    }
+}
@@ -0,0 +1,124 @@
+use std::borrow::Cow;
+use std::io::Read;
+use std::path::Path;
+
+use ammonia::{Url, UrlRelative, UrlRelativeEvaluate};
+use minijinja::Value;
+use pulldown_cmark::{Options, Parser};
+
+use crate::links::Confinement;
+use crate::templates::Readme;
+use crate::vfs::FileSystem;
+use crate::visibility::Rules;
+
+/// The names looked for, in order of preference.
+const NAMES: [&str; 6] = ["README.md", "Readme.md", "readme.md", "README.txt", "Readme.txt", "readme.txt"];
+
+/// READMEs larger than this are left out rather than rendered.
+const SIZE_LIMIT: u64 = 1024 * 1024;
+
+/// A base for resolving relative links, whose origin is stripped again
+/// afterwards.
+const PLACEHOLDER_ORIGIN: &str = "http://readme.invalid";
+
+/// Finds and renders the README of `dir`, if it has one that `rules` do not
+/// hide and `confine` lets it be read from. `dir_url` is the directory's
+/// URL, ending in '/', which relative links and images in Markdown are
+/// resolved against.
+pub fn render(fs: &dyn FileSystem, dir: &Path, dir_url: &str, rules: &Rules, confine: &Confinement) -> Option<Readme> {
+    let (name, len) = NAMES.iter().filter(|name| !rules.hides(name, false)).find_map(|name| {
+        let path = dir.join(name);
+        let metadata = fs.metadata(&path).ok()?;
+        let admitted = confine.admits(&fs.canonicalize(&path).ok()?, || fs.read_link(&path).is_ok());
+        (metadata.is_file() && admitted).then_some((name.to_string(), metadata.len))
+    })?;
+    if len > SIZE_LIMIT {
+        return None;
+    }
+    let mut contents = Vec::new();
+    fs.open(&dir.join(&name)).ok()?.take(SIZE_LIMIT).read_to_end(&mut contents).ok()?;
+    let text = String::from_utf8_lossy(&contents).into_owned();
+    if name.ends_with(".md") {
+        Some(Readme { name, html: Some(Value::from_safe_string(markdown_to_html(&text, dir_url))), text: None })
+    } else {
+        Some(Readme { name, html: None, text: Some(text) })
+    }
+}
+
+/// Converts Markdown to HTML with scripts, styles and event handlers
+/// stripped, and with relative URLs made absolute against `dir_url`.
+fn markdown_to_html(markdown: &str, dir_url: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, Parser::new_ext(markdown, options));
+
+    let base = Url::parse(PLACEHOLDER_ORIGIN).and_then(|origin| origin.join(dir_url));
+    let mut builder = ammonia::Builder::default();
+    if let Ok(base) = base {
+        builder.url_relative(UrlRelative::Custom(Box::new(Rebase(base))));
+    }
+    builder.clean(&html).to_string()
+}
+
+/// Resolves relative URLs against the directory's URL, on the placeholder
+/// origin, keeping in-page anchors as they are and dropping URLs that do
+/// not resolve.
+struct Rebase(Url);
+
+impl UrlRelativeEvaluate<'_> for Rebase {
+    fn evaluate<'url>(&self, url: &'url str) -> Option<Cow<'url, str>> {
+        if url.starts_with('#') {
+            return Some(Cow::Borrowed(url));
+        }
+        let resolved = self.0.join(url).ok()?;
+        if resolved.origin() != self.0.origin() {
+            // Scheme-relative URLs such as //example.com/ lead elsewhere.
+            return Some(Cow::Borrowed(url));
+        }
+        Some(Cow::Owned(resolved[ammonia::url::Position::BeforePath..].to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::links::SymlinkPolicy;
+    use crate::vfs::MemoryFs;
+    use crate::visibility::Visibility;
+
+    #[test]
+    fn test_markdown_is_sanitised_and_links_rewritten() {
+        let markdown = "# Title\n\n[guide](guide/intro.md) [up](../other/) [top](#title) [site](https://example.com/a)\n\n\
+            ![logo](img/logo%20v2.png)\n\n<script>alert(1)</script><a href=\"x\" onclick=\"evil()\">x</a>\n";
+        let html = markdown_to_html(markdown, "/docs/my%20project/");
+        assert!(html.contains("<h1>Title</h1>"));
+        assert!(html.contains("href=\"/docs/my%20project/guide/intro.md\""));
+        assert!(html.contains("href=\"/docs/other/\""));
+        assert!(html.contains("href=\"#title\""));
+        assert!(html.contains("href=\"https://example.com/a\""));
+        assert!(html.contains("src=\"/docs/my%20project/img/logo%20v2.png\""));
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("onclick"));
+    }
+
+    #[test]
+    fn test_render_prefers_markdown() {
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/docs/README.md", "*hello*");
+        fs.add_file("/srv/docs/README.txt", "plain");
+        fs.add_file("/srv/notes/readme.txt", "<plain>");
+        let rules = Visibility::default().rules(&fs, Path::new("/srv"), "");
+        let confine = Confinement { root: PathBuf::from("/srv"), policy: SymlinkPolicy::Inside };
+
+        let readme = render(&fs, Path::new("/srv/docs"), "/docs/", &rules, &confine).unwrap();
+        assert_eq!(readme.name, "README.md");
+        assert_eq!(readme.html.unwrap().to_string(), "<p><em>hello</em></p>\n");
+
+        let readme = render(&fs, Path::new("/srv/notes"), "/notes/", &rules, &confine).unwrap();
+        assert_eq!(readme.name, "readme.txt");
+        assert_eq!(readme.text.as_deref(), Some("<plain>"));
+        assert!(render(&fs, Path::new("/srv"), "/", &rules, &confine).is_none());
+    }
+}
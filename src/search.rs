@@ -0,0 +1,316 @@
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::filter::NameFilter;
+use crate::links::{self, Confinement};
+use crate::sort::natural_cmp;
+use crate::vfs::{FileSystem, Metadata};
+use crate::visibility::Rules;
+
+/// Bounds on how much of the tree a single search may walk.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub max_depth: usize,
+    pub max_results: usize,
+    pub timeout: Duration,
+}
+
+/// A matching entry found below the starting directory.
+#[derive(Debug)]
+pub struct Hit {
+    /// The path relative to the directory the search started in.
+    pub relative: PathBuf,
+    pub is_dir: bool,
+    pub size: Option<u64>,
+}
+
+/// Why a search returned before it had seen the whole tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stopped {
+    ResultLimit,
+    TimeLimit,
+}
+
+#[derive(Debug, Default)]
+pub struct Outcome {
+    pub hits: Vec<Hit>,
+    pub stopped: Option<Stopped>,
+    /// Whether some directories were left out for being deeper than allowed.
+    pub depth_limited: bool,
+}
+
+/// An entry reached by a [`Walk`].
+pub struct Step {
+    pub path: PathBuf,
+    /// The path relative to the directory the walk started in.
+    pub relative: PathBuf,
+    pub metadata: Metadata,
+    /// The link's target, relative to the link, when the walk keeps links
+    /// and the entry is one that stays below the start.
+    pub link: Option<PathBuf>,
+}
+
+/// A breadth-first walk of the tree below a directory, one entry at a time.
+/// Entries the confinement does not admit (symlinks pointing out of the
+/// served root, or any symlink when links are not followed) are skipped,
+/// and each real directory is entered at most once. Hidden and ignored
+/// entries are skipped along with everything below them. The walk does not
+/// hold on to the filesystem, so a caller that owns both can keep it across
+/// reads.
+pub struct Walk {
+    start: PathBuf,
+    confine: Confinement,
+    max_depth: usize,
+    visited: HashSet<PathBuf>,
+    /// Directories still to be listed, with their relative path, depth and
+    /// the rules for their entries.
+    queue: VecDeque<(PathBuf, PathBuf, usize, Rules)>,
+    /// Entries of the directory being listed, in natural name order.
+    pending: VecDeque<PathBuf>,
+    current: (PathBuf, usize, Rules),
+    /// Whether symlinks are reported as links rather than entered.
+    keep_links: bool,
+    /// The paths, relative to the start, the walk is limited to.
+    selection: Option<Vec<PathBuf>>,
+    /// Whether some directories were left out for being deeper than allowed.
+    pub depth_limited: bool,
+}
+
+impl Walk {
+    /// Walks the tree below `start`, whose entries `rules` applies to.
+    pub fn new(fs: &dyn FileSystem, start: &Path, confine: &Confinement, max_depth: usize, rules: Rules) -> Walk {
+        let mut visited = HashSet::new();
+        if let Ok(real) = fs.canonicalize(start) {
+            visited.insert(real);
+        }
+        Walk {
+            start: start.to_path_buf(),
+            confine: confine.clone(),
+            max_depth,
+            visited,
+            queue: VecDeque::from([(start.to_path_buf(), PathBuf::new(), 0, rules.clone())]),
+            pending: VecDeque::new(),
+            current: (PathBuf::new(), 0, rules),
+            keep_links: false,
+            selection: None,
+            depth_limited: false,
+        }
+    }
+
+    /// Reports admitted symlinks that lead somewhere below the start with
+    /// their target in [`Step::link`] and leaves those directories unentered,
+    /// for archives that store links. Links leading elsewhere are followed,
+    /// as a link to them would not survive outside the walked tree.
+    pub fn keeping_links(self) -> Walk {
+        Walk { keep_links: true, ..self }
+    }
+
+    /// Limits the walk to `paths`, relative to the start, and everything
+    /// below them. The directories leading to them are reported too, but
+    /// only entered on their way.
+    pub fn only(self, paths: Vec<PathBuf>) -> Walk {
+        Walk { selection: Some(paths), ..self }
+    }
+
+    /// The target of the link at `path`, rewritten relative to the link,
+    /// when it leads somewhere below the start.
+    fn kept_link(&self, fs: &dyn FileSystem, path: &Path, relative: &Path) -> Option<PathBuf> {
+        let target = links::lexical_target(path, &fs.read_link(path).ok()?)?;
+        Some(links::relative_link(relative, target.strip_prefix(&self.start).ok()?))
+    }
+
+    pub fn next_step(&mut self, fs: &dyn FileSystem) -> Option<Step> {
+        loop {
+            let Some(path) = self.pending.pop_front() else {
+                let (dir, relative_dir, depth, rules) = self.queue.pop_front()?;
+                let Ok(entries) = fs.read_dir(&dir) else { continue };
+                let mut children = entries.collect::<Vec<_>>();
+                children.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+                self.pending = children.into();
+                self.current = (relative_dir, depth, rules);
+                continue;
+            };
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else { continue };
+            let Ok(real) = fs.canonicalize(&path) else { continue };
+            if !self.confine.admits(&real, || fs.read_link(&path).is_ok()) {
+                continue;
+            }
+            let Ok(metadata) = fs.metadata(&path) else { continue };
+            let (relative_dir, depth, rules) = &self.current;
+            if rules.hides(name, metadata.is_dir()) {
+                continue;
+            }
+            let relative = relative_dir.join(name);
+            if let Some(selection) = &self.selection
+                && !selection.iter().any(|selected| relative.starts_with(selected) || selected.starts_with(&relative))
+            {
+                continue;
+            }
+            let link = if self.keep_links { self.kept_link(fs, &path, &relative) } else { None };
+            if metadata.is_dir() && link.is_none() && self.visited.insert(real) {
+                if *depth < self.max_depth {
+                    self.queue.push_back((path.clone(), relative.clone(), depth + 1, rules.descend(fs, name)));
+                } else {
+                    self.depth_limited = true;
+                }
+            }
+            return Some(Step { path, relative, metadata, link });
+        }
+    }
+}
+
+/// Collects the entries below `start` whose names pass `filter`.
+pub fn find(fs: &dyn FileSystem, start: &Path, confine: &Confinement, rules: Rules, filter: &NameFilter, limits: Limits) -> Outcome {
+    let deadline = Instant::now() + limits.timeout;
+    let mut outcome = Outcome::default();
+    let mut walk = Walk::new(fs, start, confine, limits.max_depth, rules);
+    while let Some(step) = walk.next_step(fs) {
+        if Instant::now() >= deadline {
+            outcome.stopped = Some(Stopped::TimeLimit);
+            break;
+        }
+        if !step.relative.file_name().and_then(|name| name.to_str()).is_some_and(|name| filter.matches(name)) {
+            continue;
+        }
+        if outcome.hits.len() == limits.max_results {
+            outcome.stopped = Some(Stopped::ResultLimit);
+            break;
+        }
+        outcome.hits.push(Hit {
+            relative: step.relative,
+            is_dir: step.metadata.is_dir(),
+            size: step.metadata.is_file().then_some(step.metadata.len),
+        });
+    }
+    outcome.depth_limited = walk.depth_limited;
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::Query;
+    use crate::links::SymlinkPolicy;
+    use crate::vfs::MemoryFs;
+    use crate::visibility::Visibility;
+
+    const LIMITS: Limits = Limits { max_depth: 16, max_results: 100, timeout: Duration::from_secs(60) };
+
+    fn confine(root: &Path, policy: SymlinkPolicy) -> Confinement {
+        Confinement { root: root.to_path_buf(), policy }
+    }
+
+    fn tree() -> MemoryFs {
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/build.log", "x");
+        fs.add_file("/srv/logs/2024/app.log", "xyz");
+        fs.add_file("/srv/logs/2024/notes.txt", "");
+        fs.add_file("/srv/logs/app.log", "");
+        fs.add_dir("/srv/app-logs");
+        fs.add_file("/srv/.cache/old.log", "");
+        fs
+    }
+
+    fn search(fs: &MemoryFs, raw_query: &str, limits: Limits) -> Outcome {
+        let filter = NameFilter::from_query(&Query::parse(raw_query)).unwrap();
+        let rules = Visibility::default().rules(fs, Path::new("/srv"), "");
+        find(fs, Path::new("/srv"), &confine(Path::new("/srv"), SymlinkPolicy::Inside), rules, &filter, limits)
+    }
+
+    fn relative_paths(outcome: &Outcome) -> Vec<String> {
+        outcome.hits.iter().map(|hit| hit.relative.display().to_string()).collect()
+    }
+
+    #[test]
+    fn test_find_by_glob_breadth_first() {
+        let outcome = search(&tree(), "filter=*.log", LIMITS);
+        assert_eq!(relative_paths(&outcome), vec!["build.log", "logs/app.log", "logs/2024/app.log"]);
+        assert_eq!(outcome.hits[2].size, Some(3));
+        assert_eq!(outcome.stopped, None);
+        assert!(!outcome.depth_limited);
+    }
+
+    #[test]
+    fn test_walk_limited_to_a_selection() {
+        let fs = tree();
+        let rules = Visibility::default().rules(&fs, Path::new("/srv"), "");
+        let confine = confine(Path::new("/srv"), SymlinkPolicy::Inside);
+        let selection = vec![PathBuf::from("build.log"), PathBuf::from("logs/2024")];
+        let mut walk = Walk::new(&fs, Path::new("/srv"), &confine, usize::MAX, rules).only(selection);
+        let mut reached = Vec::new();
+        while let Some(step) = walk.next_step(&fs) {
+            reached.push(step.relative.display().to_string());
+        }
+        assert_eq!(reached, ["build.log", "logs", "logs/2024", "logs/2024/app.log", "logs/2024/notes.txt"]);
+    }
+
+    #[test]
+    fn test_find_by_substring_includes_directories() {
+        let outcome = search(&tree(), "q=app", LIMITS);
+        assert_eq!(relative_paths(&outcome), vec!["app-logs", "logs/app.log", "logs/2024/app.log"]);
+        assert!(outcome.hits[0].is_dir);
+    }
+
+    #[test]
+    fn test_find_skips_hidden_entries() {
+        let fs = tree();
+        let filter = NameFilter::from_query(&Query::parse("filter=*.log")).unwrap();
+        let rules = Visibility { show_hidden: true, ..Visibility::default() }.rules(&fs, Path::new("/srv"), "");
+        let outcome = find(&fs, Path::new("/srv"), &confine(Path::new("/srv"), SymlinkPolicy::Inside), rules, &filter, LIMITS);
+        assert!(relative_paths(&outcome).contains(&".cache/old.log".to_string()));
+        assert!(!relative_paths(&search(&fs, "filter=*.log", LIMITS)).contains(&".cache/old.log".to_string()));
+    }
+
+    #[test]
+    fn test_find_respects_depth_limit() {
+        let outcome = search(&tree(), "filter=*.log", Limits { max_depth: 1, ..LIMITS });
+        assert_eq!(relative_paths(&outcome), vec!["build.log", "logs/app.log"]);
+        assert!(outcome.depth_limited);
+    }
+
+    #[test]
+    fn test_find_respects_result_limit() {
+        let outcome = search(&tree(), "filter=*.log", Limits { max_results: 2, ..LIMITS });
+        assert_eq!(relative_paths(&outcome), vec!["build.log", "logs/app.log"]);
+        assert_eq!(outcome.stopped, Some(Stopped::ResultLimit));
+    }
+
+    #[test]
+    fn test_find_respects_time_limit() {
+        let outcome = search(&tree(), "filter=*.log", Limits { timeout: Duration::ZERO, ..LIMITS });
+        assert!(outcome.hits.is_empty());
+        assert_eq!(outcome.stopped, Some(Stopped::TimeLimit));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_skips_symlinks_out_of_root() {
+        use crate::vfs::LocalFs;
+
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("secret.log"), "").unwrap();
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir(root.path().join("inside")).unwrap();
+        std::fs::write(root.path().join("inside/kept.log"), "").unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.path().join("escape")).unwrap();
+        std::os::unix::fs::symlink(root.path().join("inside"), root.path().join("alias")).unwrap();
+
+        let root = root.path().canonicalize().unwrap();
+        let filter = NameFilter::from_query(&Query::parse("filter=*.log")).unwrap();
+        let walk = |policy| find(&LocalFs, &root, &confine(&root, policy), Visibility::default().rules(&LocalFs, &root, ""), &filter, LIMITS);
+        let outcome = walk(SymlinkPolicy::Inside);
+        assert_eq!(outcome.hits.len(), 1);
+        assert!(outcome.hits[0].relative.ends_with("kept.log"));
+
+        let found = relative_paths(&walk(SymlinkPolicy::Anywhere));
+        assert_eq!(found.len(), 2);
+        assert!(found.contains(&"escape/secret.log".to_string()));
+
+        let outcome = walk(SymlinkPolicy::Never);
+        assert_eq!(relative_paths(&outcome), vec!["inside/kept.log"]);
+    }
+}
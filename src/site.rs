@@ -0,0 +1,77 @@
+use std::path::{Path, PathBuf};
+
+use crate::vfs::FileSystem;
+use crate::visibility::Rules;
+
+/// The names looked for in a directory when `--index-files` is not given.
+pub const DEFAULT_INDEX_FILES: [&str; 2] = ["index.html", "index.htm"];
+
+/// Settings for serving the root as a static web site instead of browsing
+/// it, as chosen with `--static`.
+#[derive(Debug, Clone)]
+pub struct StaticSite {
+    /// File names served in place of a directory's listing, in order of
+    /// preference.
+    pub index_files: Vec<String>,
+    /// The file served, with 200 OK, for paths that do not exist, so that a
+    /// single-page application can route them itself.
+    pub fallback: Option<PathBuf>,
+}
+
+impl StaticSite {
+    /// The first index file present in `dir`, skipping any that `rules` hide.
+    pub fn index_file(&self, fs: &dyn FileSystem, dir: &Path, rules: &Rules) -> Option<PathBuf> {
+        self.index_files
+            .iter()
+            .filter(|name| !rules.hides(name, false))
+            .map(|name| dir.join(name))
+            .find(|path| fs.metadata(path).is_ok_and(|metadata| metadata.is_file()))
+    }
+}
+
+/// The types a browser needs to render a site, which the explorer otherwise
+/// serves as plain text so that browsing never runs the served files.
+pub fn mime_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    Some(match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "wasm" => "application/wasm",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "txt" => "text/plain; charset=utf-8",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+    use crate::visibility::Visibility;
+
+    #[test]
+    fn test_index_file_prefers_earlier_names() {
+        let fs = MemoryFs::new();
+        fs.add_file("/site/index.htm", b"old");
+        fs.add_file("/site/docs/index.html", b"docs");
+        fs.add_file("/site/docs/index.htm", b"old docs");
+        fs.add_file("/site/blog/post.html", b"post");
+        let site = StaticSite { index_files: DEFAULT_INDEX_FILES.map(str::to_string).to_vec(), fallback: None };
+        let rules = Visibility::default().rules(&fs, Path::new("/site"), "");
+
+        assert_eq!(site.index_file(&fs, Path::new("/site/docs"), &rules), Some(PathBuf::from("/site/docs/index.html")));
+        assert_eq!(site.index_file(&fs, Path::new("/site"), &rules), Some(PathBuf::from("/site/index.htm")));
+        assert_eq!(site.index_file(&fs, Path::new("/site/blog"), &rules), None);
+    }
+
+    #[test]
+    fn test_mime_type() {
+        assert_eq!(mime_type(Path::new("index.HTML")), Some("text/html; charset=utf-8"));
+        assert_eq!(mime_type(Path::new("app.mjs")), Some("text/javascript; charset=utf-8"));
+        assert_eq!(mime_type(Path::new("photo.jpg")), None);
+        assert_eq!(mime_type(Path::new("Makefile")), None);
+    }
+}
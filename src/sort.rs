@@ -0,0 +1,382 @@
+use std::cmp::Ordering;
+
+use serde::Serialize;
+
+use crate::http::Query;
+use crate::templates::Entry;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    Name,
+    Size,
+    Mtime,
+    Type,
+    /// The order the directory is read in (`?sort=none`), which lets huge
+    /// listings be sent before the whole directory has been read.
+    #[serde(rename = "none")]
+    Unsorted,
+}
+
+impl SortKey {
+    /// The keys a listing can be sorted by, one per column.
+    pub const ALL: [SortKey; 4] = [SortKey::Name, SortKey::Size, SortKey::Mtime, SortKey::Type];
+
+    fn parse(value: &str) -> Option<SortKey> {
+        match value {
+            "name" => Some(SortKey::Name),
+            "size" => Some(SortKey::Size),
+            "mtime" => Some(SortKey::Mtime),
+            "type" => Some(SortKey::Type),
+            "none" => Some(SortKey::Unsorted),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SortKey::Name => "name",
+            SortKey::Size => "size",
+            SortKey::Mtime => "mtime",
+            SortKey::Type => "type",
+            SortKey::Unsorted => "none",
+        }
+    }
+}
+
+/// What sorting looks at in a listing row, so rows can be put in order
+/// before the work of formatting them is done.
+pub trait Sortable {
+    fn name(&self) -> &str;
+    fn is_dir(&self) -> bool;
+    fn size(&self) -> Option<u64>;
+    /// Seconds since the Unix epoch.
+    fn modified(&self) -> Option<i64>;
+    fn mime_type(&self) -> Option<&'static str>;
+}
+
+impl Sortable for Entry {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    fn modified(&self) -> Option<i64> {
+        self.modified
+    }
+
+    fn mime_type(&self) -> Option<&'static str> {
+        self.mime_type
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    fn as_str(self) -> &'static str {
+        match self {
+            SortOrder::Asc => "asc",
+            SortOrder::Desc => "desc",
+        }
+    }
+}
+
+/// How a listing is ordered, as chosen by `?sort=`, `?order=` and `?dirs=`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Sort {
+    pub key: SortKey,
+    pub order: SortOrder,
+    /// Whether directories are grouped ahead of files regardless of the key.
+    pub dirs_first: bool,
+}
+
+impl Default for Sort {
+    fn default() -> Self {
+        Sort { key: SortKey::Name, order: SortOrder::Asc, dirs_first: true }
+    }
+}
+
+impl Sort {
+    /// Reads the sort parameters, falling back to the defaults for anything
+    /// missing or unrecognised.
+    pub fn from_query(query: &Query) -> Sort {
+        let default = Sort::default();
+        Sort {
+            key: query.get("sort").and_then(SortKey::parse).unwrap_or(default.key),
+            order: match query.get("order") {
+                Some("desc") => SortOrder::Desc,
+                Some("asc") => SortOrder::Asc,
+                _ => default.order,
+            },
+            dirs_first: query.get("dirs") != Some("mixed"),
+        }
+    }
+
+    /// The query string selecting this sort, starting with '?'.
+    pub fn to_query(self) -> String {
+        let mut query = format!("?sort={}&order={}", self.key.as_str(), self.order.as_str());
+        if !self.dirs_first {
+            query.push_str("&dirs=mixed");
+        }
+        query
+    }
+
+    /// The sort a column header links to: ascending on a new column, or the
+    /// reverse order when it is already the active one.
+    pub fn toggled(self, key: SortKey) -> Sort {
+        let order = match (self.key == key, self.order) {
+            (true, SortOrder::Asc) => SortOrder::Desc,
+            _ => SortOrder::Asc,
+        };
+        Sort { key, order, ..self }
+    }
+
+    /// Sorts `entries` in place; unsorted listings are left as they are.
+    pub fn sort<T: Sortable>(self, entries: &mut [T]) {
+        if self.key == SortKey::Unsorted {
+            return;
+        }
+        entries.sort_by(|a, b| self.compare(a, b));
+    }
+
+    /// The first `count` of `entries` in this order. At most twice that many
+    /// are held at a time, so a page can be picked out of a huge directory
+    /// without keeping all of it. Unsorted listings keep the first `count`.
+    pub fn first<T: Sortable>(self, entries: impl Iterator<Item = T>, count: usize) -> Vec<T> {
+        if self.key == SortKey::Unsorted {
+            return entries.take(count).collect();
+        }
+        let capacity = count.saturating_mul(2).max(1);
+        let mut kept = Vec::new();
+        for entry in entries {
+            kept.push(entry);
+            if kept.len() >= capacity {
+                self.sort(&mut kept);
+                kept.truncate(count);
+            }
+        }
+        self.sort(&mut kept);
+        kept.truncate(count);
+        kept
+    }
+
+    fn compare<T: Sortable>(self, a: &T, b: &T) -> Ordering {
+        if self.dirs_first && a.is_dir() != b.is_dir() {
+            return b.is_dir().cmp(&a.is_dir());
+        }
+        let ordering = match self.key {
+            SortKey::Name | SortKey::Unsorted => Ordering::Equal,
+            SortKey::Size => a.size().cmp(&b.size()),
+            SortKey::Mtime => a.modified().cmp(&b.modified()),
+            SortKey::Type => a.mime_type().cmp(&b.mime_type()),
+        }
+        .then_with(|| natural_cmp(a.name(), b.name()));
+        match self.order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        }
+    }
+}
+
+/// Compares names the way people read them: runs of digits by their numeric
+/// value, so `file2` sorts before `file10`, and letters without regard to case.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut left = a.chars().peekable();
+    let mut right = b.chars().peekable();
+    loop {
+        match (left.peek().copied(), right.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(l), Some(r)) if l.is_ascii_digit() && r.is_ascii_digit() => {
+                let left_digits = take_digits(&mut left);
+                let right_digits = take_digits(&mut right);
+                let left_value = left_digits.trim_start_matches('0');
+                let right_value = right_digits.trim_start_matches('0');
+                let ordering = left_value
+                    .len()
+                    .cmp(&right_value.len())
+                    .then_with(|| left_value.cmp(right_value))
+                    .then_with(|| left_digits.len().cmp(&right_digits.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(l), Some(r)) => {
+                let ordering = l.to_lowercase().cmp(r.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                left.next();
+                right.next();
+            }
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+        digits.push(digit);
+    }
+    digits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::templates::url_value;
+
+    fn entry(name: &str, is_dir: bool, size: Option<u64>, modified: Option<i64>) -> Entry {
+        Entry {
+            name: name.to_string(),
+            url: url_value(format!("/{}", name)),
+            is_dir,
+            is_archive: false,
+            size,
+            size_human: None,
+            modified,
+            modified_display: None,
+            modified_iso: None,
+            modified_relative: None,
+            mime_type: None,
+            permissions: None,
+            link_target: None,
+            broken_link: false,
+        }
+    }
+
+    fn names(entries: &[Entry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        let mut names = vec!["file10.txt", "File2.txt", "file1.txt", "file02.txt", "a", "file2.txt"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, vec!["a", "file1.txt", "File2.txt", "file2.txt", "file02.txt", "file10.txt"]);
+        assert_eq!(natural_cmp("v1.10", "v1.9"), Ordering::Greater);
+        assert_eq!(natural_cmp("same", "same"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_from_query() {
+        assert_eq!(Sort::from_query(&Query::parse("")), Sort::default());
+        let sort = Sort::from_query(&Query::parse("sort=size&order=desc&dirs=mixed"));
+        assert_eq!(sort, Sort { key: SortKey::Size, order: SortOrder::Desc, dirs_first: false });
+        assert_eq!(Sort::from_query(&Query::parse("sort=bogus&order=sideways")), Sort::default());
+        assert_eq!(Sort::from_query(&Query::parse("sort=none")).key, SortKey::Unsorted);
+    }
+
+    #[test]
+    fn test_toggled_and_to_query() {
+        let sort = Sort::default();
+        assert_eq!(sort.toggled(SortKey::Name).to_query(), "?sort=name&order=desc");
+        assert_eq!(sort.toggled(SortKey::Size).to_query(), "?sort=size&order=asc");
+        let mixed = Sort { dirs_first: false, ..sort.toggled(SortKey::Name) };
+        assert_eq!(mixed.toggled(SortKey::Name).to_query(), "?sort=name&order=asc&dirs=mixed");
+    }
+
+    #[test]
+    fn test_sort_by_size_keeps_directories_first() {
+        let mut entries = vec![
+            entry("big", false, Some(300), None),
+            entry("docs", true, None, None),
+            entry("small", false, Some(10), None),
+        ];
+        Sort { key: SortKey::Size, order: SortOrder::Desc, dirs_first: true }.sort(&mut entries);
+        assert_eq!(names(&entries), vec!["docs", "big", "small"]);
+
+        Sort { key: SortKey::Size, order: SortOrder::Asc, dirs_first: false }.sort(&mut entries);
+        assert_eq!(names(&entries), vec!["docs", "small", "big"]);
+    }
+
+    #[test]
+    fn test_sort_by_mtime_breaks_ties_by_name() {
+        let mut entries = vec![
+            entry("b", false, None, Some(5)),
+            entry("c", false, None, Some(1)),
+            entry("a", false, None, Some(5)),
+        ];
+        Sort { key: SortKey::Mtime, order: SortOrder::Asc, dirs_first: true }.sort(&mut entries);
+        assert_eq!(names(&entries), vec!["c", "a", "b"]);
+    }
+
+    #[test]
+    fn test_first_matches_a_full_sort() {
+        let entries = || (0..500u64).map(|i| entry(&format!("file{}", (i * 7919) % 500), i % 13 == 0, Some(i % 17), None));
+        for sort in [Sort::default(), Sort { key: SortKey::Size, order: SortOrder::Desc, dirs_first: false }] {
+            let mut all = entries().collect::<Vec<_>>();
+            sort.sort(&mut all);
+            for count in [0, 1, 25, 499, 500, 1000] {
+                let first = sort.first(entries(), count);
+                let names = first.iter().map(|entry| entry.name.as_str()).collect::<Vec<_>>();
+                let expected = all.iter().take(count).map(|entry| entry.name.as_str()).collect::<Vec<_>>();
+                assert_eq!(names, expected, "{:?} {}", sort, count);
+            }
+        }
+    }
+
+    #[test]
+    fn test_first_holds_a_bounded_number_of_entries() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        /// Counts how many of its kind are alive, and the most there ever were.
+        struct Tracked(String, Rc<(Cell<usize>, Cell<usize>)>);
+        impl Tracked {
+            fn new(name: String, live: &Rc<(Cell<usize>, Cell<usize>)>) -> Tracked {
+                live.0.set(live.0.get() + 1);
+                live.1.set(live.1.get().max(live.0.get()));
+                Tracked(name, live.clone())
+            }
+        }
+        impl Drop for Tracked {
+            fn drop(&mut self) {
+                self.1.0.set(self.1.0.get() - 1);
+            }
+        }
+        impl Sortable for Tracked {
+            fn name(&self) -> &str {
+                &self.0
+            }
+            fn is_dir(&self) -> bool {
+                false
+            }
+            fn size(&self) -> Option<u64> {
+                None
+            }
+            fn modified(&self) -> Option<i64> {
+                None
+            }
+            fn mime_type(&self) -> Option<&'static str> {
+                None
+            }
+        }
+
+        let live = Rc::new((Cell::new(0), Cell::new(0)));
+        let entries = (0..100_000).rev().map(|i| Tracked::new(format!("file{}", i), &live));
+        let first = Sort::default().first(entries, 50);
+        assert_eq!(first.iter().map(Sortable::name).take(2).collect::<Vec<_>>(), ["file0", "file1"]);
+        assert!(live.1.get() <= 101, "{} entries were held at once", live.1.get());
+    }
+
+    #[test]
+    fn test_unsorted_keeps_directory_order() {
+        let mut entries = vec![entry("b", false, None, None), entry("docs", true, None, None), entry("a", false, None, None)];
+        Sort { key: SortKey::Unsorted, ..Sort::default() }.sort(&mut entries);
+        assert_eq!(names(&entries), vec!["b", "docs", "a"]);
+    }
+}
@@ -0,0 +1,346 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use minijinja::{Environment, Value};
+use serde::Serialize;
+
+use crate::search::Stopped;
+use crate::sort::Sort;
+
+/// The built-in templates, by name. A directory of overrides may replace any
+/// of them or add new ones for the built-ins to include or extend.
+const BUILTIN_TEMPLATES: [(&str, &str); 10] = [
+    ("base.html", include_str!("../templates/base.html")),
+    ("listing.html", include_str!("../templates/listing.html")),
+    ("listing_rows.html", include_str!("../templates/listing_rows.html")),
+    ("autoindex.html", include_str!("../templates/autoindex.html")),
+    ("preview.html", include_str!("../templates/preview.html")),
+    ("search.html", include_str!("../templates/search.html")),
+    ("grep.html", include_str!("../templates/grep.html")),
+    ("grep_results.html", include_str!("../templates/grep_results.html")),
+    ("find.html", include_str!("../templates/find.html")),
+    ("error.html", include_str!("../templates/error.html")),
+];
+
+/// Renders HTML pages from templates, escaping every value unless it is
+/// explicitly marked as safe.
+pub struct Templates {
+    env: Environment<'static>,
+}
+
+impl Templates {
+    /// Loads the built-in templates, then every `.html` file in `override_dir`.
+    pub fn new(override_dir: Option<&Path>) -> io::Result<Templates> {
+        let mut env = Environment::new();
+        env.set_trim_blocks(true);
+        env.set_lstrip_blocks(true);
+        for (name, source) in BUILTIN_TEMPLATES {
+            env.add_template(name, source).map_err(io::Error::other)?;
+        }
+        if let Some(dir) = override_dir {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.extension().and_then(|ext| ext.to_str()) != Some("html") {
+                    continue;
+                }
+                let Some(name) = path.file_name().and_then(|name| name.to_str()).map(str::to_string) else { continue };
+                let source = fs::read_to_string(&path)?;
+                env.add_template_owned(name, source).map_err(|err| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), err))
+                })?;
+            }
+        }
+        Ok(Templates { env })
+    }
+
+    pub fn render(&self, name: &str, context: impl Serialize) -> Result<String, minijinja::Error> {
+        self.env.get_template(name)?.render(context)
+    }
+
+    /// Renders a single macro defined at the top level of template `name`,
+    /// for pages that are sent in pieces.
+    pub fn call_macro(&self, name: &str, macro_name: &str, argument: Value) -> Result<String, minijinja::Error> {
+        self.env.get_template(name)?.render_captured(())?.state().call_macro(macro_name, &[argument])
+    }
+}
+
+/// Marks an already percent-encoded URL as safe, so it is not HTML-escaped
+/// a second time (which would turn every '/' into an entity).
+pub fn url_value(url: String) -> Value {
+    Value::from_safe_string(url)
+}
+
+/// A link in the trail from the served root down to the current directory.
+#[derive(Debug, Serialize)]
+pub struct Breadcrumb {
+    /// The segment's decoded name, or `/` for the served root.
+    pub name: String,
+    pub url: Value,
+}
+
+/// One row of a directory listing.
+#[derive(Debug, Serialize)]
+pub struct Entry {
+    pub name: String,
+    pub url: Value,
+    pub is_dir: bool,
+    pub is_archive: bool,
+    pub size: Option<u64>,
+    pub size_human: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub modified: Option<i64>,
+    pub modified_display: Option<String>,
+    pub modified_iso: Option<String>,
+    /// How long ago the entry changed, relative to when the page was rendered.
+    pub modified_relative: Option<String>,
+    /// Only set for files.
+    pub mime_type: Option<&'static str>,
+    /// In `ls -l` form, e.g. `-rw-r--r--`.
+    pub permissions: Option<String>,
+    /// Where the entry points, as written in the link, when it is a symlink.
+    /// With `--hide-path` it is a path from the served root instead, and
+    /// `None` when the link leads out of it.
+    pub link_target: Option<String>,
+    /// Whether the entry is a symlink to something that does not exist.
+    pub broken_link: bool,
+}
+
+/// Context for `listing.html`. On the HTML page the rows are rendered
+/// separately by the macros of `listing_rows.html` and streamed in where
+/// `rows` appears; the other formats read `entries`.
+#[derive(Debug, Serialize)]
+pub struct ListingContext {
+    /// The directory's absolute path on the server, unless the server was
+    /// started with `--hide-path`.
+    pub path: Option<String>,
+    /// Whether `path` carries the Windows `\\?\` long path prefix.
+    pub long_path: bool,
+    pub parent_url: Option<Value>,
+    pub breadcrumbs: Vec<Breadcrumb>,
+    /// The requested page of the listing, or all of it.
+    pub entries: Vec<Entry>,
+    /// How many entries the directory holds before filtering.
+    pub total_entries: usize,
+    /// How many entries passed the filter, on all pages.
+    pub matched_entries: usize,
+    /// Whether the listing was narrowed by `filter` or `q`.
+    pub filtered: bool,
+    pub sort: Sort,
+    /// For each sort key, the query string a click on its column header
+    /// leads to.
+    pub sort_links: BTreeMap<&'static str, Value>,
+    /// The query string switching directories-first grouping on or off.
+    pub dirs_toggle_url: Value,
+    /// Whether a full-text index is available to search.
+    pub indexed: bool,
+    /// Whether any matched entry is an audio or video file.
+    pub has_media: bool,
+    /// Whether any matched entry is a directory.
+    pub has_subdirs: bool,
+    /// The query string of the "Play all" playlist, keeping sort and filter.
+    pub playlist_url: Value,
+    /// The `?filter=` glob, if any.
+    pub filter: Option<String>,
+    /// The `?q=` substring, if any.
+    pub q: Option<String>,
+    pub rows: Value,
+    /// The directory's README, shown below the table.
+    pub readme: Option<Readme>,
+}
+
+/// A README rendered under a listing: Markdown as sanitised HTML, anything
+/// else as plain text.
+#[derive(Debug, Serialize)]
+pub struct Readme {
+    pub name: String,
+    pub html: Option<Value>,
+    pub text: Option<String>,
+}
+
+/// One row of `autoindex.html`.
+#[derive(Debug, Serialize)]
+pub struct AutoindexRow {
+    /// Slash-separated, unlike the listing's URLs, so crawlers see a tree.
+    pub href: Value,
+    pub name: String,
+    /// Directories are shown with a trailing '/'.
+    pub is_dir: bool,
+    /// Spaces lining up the columns after the name.
+    pub padding: String,
+    /// `2024-01-15 13:45` in UTC, or `-`.
+    pub modified: String,
+    /// Right-aligned, e.g. ` 1.5K`, or `    -` for directories.
+    pub size: String,
+}
+
+/// Context for `autoindex.html`.
+#[derive(Debug, Serialize)]
+pub struct AutoindexContext {
+    /// The directory's path below the served root, one name per level.
+    pub segments: Vec<String>,
+    pub parent_url: Option<Value>,
+    pub header_padding: String,
+    pub parent_padding: String,
+    pub rows: Vec<AutoindexRow>,
+}
+
+/// Context for `preview.html`.
+#[derive(Debug, Serialize)]
+pub struct PreviewContext {
+    pub name: String,
+    pub url: Value,
+    pub parent_url: Value,
+    pub mime_type: &'static str,
+    /// One of `image`, `video`, `text` or `other`.
+    pub kind: &'static str,
+    pub size: u64,
+    pub size_human: String,
+    pub modified: Option<String>,
+    /// The file contents, for text files small enough to show inline.
+    pub text: Option<String>,
+}
+
+/// A match on `search.html`.
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    /// The path relative to the directory being searched.
+    pub path: String,
+    pub url: Value,
+    pub is_dir: bool,
+    pub size_human: Option<String>,
+}
+
+/// Context for `search.html`.
+#[derive(Debug, Serialize)]
+pub struct SearchContext {
+    /// The searched directory relative to the served root, e.g. `/logs`.
+    pub path: String,
+    pub listing_url: Value,
+    /// Whether a name to look for was given at all.
+    pub searched: bool,
+    pub q: Option<String>,
+    pub filter: Option<String>,
+    pub depth: usize,
+    pub results: Vec<SearchResult>,
+    pub stopped: Option<Stopped>,
+    pub depth_limited: bool,
+    pub max_results: usize,
+    pub timeout_secs: u64,
+}
+
+/// Context for `grep.html`. The matches themselves are rendered separately
+/// by its macros and streamed in where `matches` appears.
+#[derive(Debug, Serialize)]
+pub struct GrepContext {
+    /// The searched directory relative to the served root, e.g. `/logs`.
+    pub path: String,
+    pub listing_url: Value,
+    pub pattern: Option<String>,
+    pub regex: bool,
+    pub icase: bool,
+    /// The size above which files are skipped, e.g. `8.0 MiB`.
+    pub file_limit: String,
+    pub matches: Value,
+}
+
+/// A ranked match on `find.html`.
+#[derive(Debug, Serialize)]
+pub struct FindResult {
+    /// The path relative to the directory being searched.
+    pub path: String,
+    pub url: Value,
+    pub score: f32,
+    pub size_human: String,
+    pub modified_display: Option<String>,
+    pub modified_iso: Option<String>,
+    pub modified_relative: Option<String>,
+}
+
+/// How current the full-text index is, as shown on `find.html`.
+#[derive(Debug, Serialize)]
+pub struct IndexStatus {
+    /// Whether the last scan succeeded recently enough to trust the results.
+    pub fresh: bool,
+    pub scanning: bool,
+    pub files: u64,
+    pub updated_display: Option<String>,
+    pub updated_relative: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Context for `find.html`.
+#[derive(Debug, Serialize)]
+pub struct FindContext {
+    /// The searched directory relative to the served root, e.g. `/logs`.
+    pub path: String,
+    pub listing_url: Value,
+    pub query: Option<String>,
+    pub results: Vec<FindResult>,
+    pub index: IndexStatus,
+}
+
+/// Context for `error.html`.
+#[derive(Debug, Serialize)]
+pub struct ErrorContext<'a> {
+    pub status: &'a str,
+    pub message: &'a str,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_templates_escape_values() {
+        let templates = Templates::new(None).unwrap();
+        let html = templates
+            .render("error.html", ErrorContext { status: "404 Not Found", message: "<script>alert(1)</script>" })
+            .unwrap();
+        assert!(html.contains("<h1>404 Not Found</h1>"));
+        assert!(html.contains("&lt;script&gt;"));
+        assert!(!html.contains("<script>"));
+    }
+
+    #[test]
+    fn test_url_values_are_not_escaped_twice() {
+        let templates = Templates::new(None).unwrap();
+        let entry = Entry {
+            name: "a&b".to_string(),
+            url: url_value("/docs%2Fa%26b".to_string()),
+            is_dir: true,
+            is_archive: false,
+            size: None,
+            size_human: None,
+            modified: None,
+            modified_display: None,
+            modified_iso: None,
+            modified_relative: None,
+            mime_type: None,
+            permissions: None,
+            link_target: None,
+            broken_link: false,
+        };
+        let html = templates.call_macro("listing_rows.html", "row", Value::from_serialize(&entry)).unwrap();
+        assert!(html.contains("<a href=\"/docs%2Fa%26b\">a&amp;b</a>"));
+    }
+
+    #[test]
+    fn test_override_directory_replaces_builtins() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("error.html"), "{% extends \"base.html\" %}{% block body %}custom {{ status }}{% endblock %}").unwrap();
+        fs::write(dir.path().join("notes.txt"), "{{ ignored").unwrap();
+        let templates = Templates::new(Some(dir.path())).unwrap();
+        let html = templates.render("error.html", ErrorContext { status: "500", message: "" }).unwrap();
+        assert!(html.contains("custom 500"));
+        assert!(html.contains("<!DOCTYPE html>"));
+    }
+
+    #[test]
+    fn test_override_directory_reports_syntax_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("listing.html"), "{% for %}").unwrap();
+        assert!(Templates::new(Some(dir.path())).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+mod memory;
+
+use std::fs;
+use std::io::{self, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+pub use memory::MemoryFs;
+
+/// The kind of an entry as seen by the explorer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    File,
+    Dir,
+}
+
+/// The subset of file metadata the handlers care about.
+#[derive(Debug, Clone)]
+pub struct Metadata {
+    pub file_type: FileType,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+    /// Unix permission bits, where the backend has them.
+    pub permissions: Option<u32>,
+}
+
+impl Metadata {
+    pub fn is_dir(&self) -> bool {
+        self.file_type == FileType::Dir
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.file_type == FileType::File
+    }
+}
+
+/// A backend the file and directory handlers can read from.
+///
+/// Paths are passed through exactly as the handlers received them; it is up to
+/// the caller to confine them to the served root before reaching the backend.
+pub trait FileSystem: Send + Sync {
+    /// Returns metadata for `path`, following symlinks.
+    fn metadata(&self, path: &Path) -> io::Result<Metadata>;
+
+    /// Lists the full paths of the entries directly inside `path`.
+    fn read_dir<'a>(&'a self, path: &Path) -> io::Result<Box<dyn Iterator<Item = PathBuf> + 'a>>;
+
+    /// Lists `path` like `read_dir`, with each entry's metadata and link
+    /// target. The iterator does not borrow the backend, so a response body
+    /// can keep reading a huge directory after the handler has returned. The
+    /// default collects everything up front; backends that can read lazily
+    /// override it.
+    fn scan_dir(&self, path: &Path) -> io::Result<DirEntries> {
+        let entries = self
+            .read_dir(path)?
+            .map(|path| DirEntry { metadata: self.metadata(&path).ok(), link: self.read_link(&path).ok(), path })
+            .collect::<Vec<_>>();
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    /// Opens `path` for reading.
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>>;
+
+    /// Opens `path` for random access. Backends that can only stream their
+    /// contents keep the default, which reports the operation as unsupported.
+    fn open_seekable(&self, path: &Path) -> io::Result<Box<dyn ReadSeek>> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, format!("{} cannot be opened for seeking", path.display())))
+    }
+
+    /// Returns where the symlink `path` points, as written in the link.
+    /// Backends without links keep the default, which reports that `path`
+    /// is not one.
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not a symbolic link", path.display())))
+    }
+
+    /// Resolves `path` to where it really lives, following symlinks.
+    /// Backends without links keep the default, which returns it unchanged.
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        Ok(path.to_path_buf())
+    }
+}
+
+/// An entry of a directory as listed by [`FileSystem::scan_dir`].
+#[derive(Debug)]
+pub struct DirEntry {
+    pub path: PathBuf,
+    /// What the entry is, following symlinks; None when that cannot be
+    /// read, as for a link whose target is missing.
+    pub metadata: Option<Metadata>,
+    /// The target of a symlink, also when it is broken.
+    pub link: Option<PathBuf>,
+}
+
+/// The entries of a directory, as returned by [`FileSystem::scan_dir`].
+pub type DirEntries = Box<dyn Iterator<Item = DirEntry> + Send>;
+
+/// A readable, seekable handle as returned by [`FileSystem::open_seekable`].
+pub trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+#[cfg(unix)]
+fn permission_bits(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o777
+}
+
+/// Approximates Unix bits from the read-only flag, which is all other
+/// platforms expose.
+#[cfg(not(unix))]
+fn permission_bits(metadata: &fs::Metadata) -> u32 {
+    if metadata.permissions().readonly() { 0o444 } else { 0o666 }
+}
+
+/// Serves straight from the local disk.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LocalFs;
+
+impl FileSystem for LocalFs {
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        let metadata = fs::metadata(path)?;
+        Ok(Metadata {
+            file_type: if metadata.is_dir() { FileType::Dir } else { FileType::File },
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            permissions: Some(permission_bits(&metadata)),
+        })
+    }
+
+    fn read_dir<'a>(&'a self, path: &Path) -> io::Result<Box<dyn Iterator<Item = PathBuf> + 'a>> {
+        let entries = fs::read_dir(path)?;
+        Ok(Box::new(entries.filter_map(Result::ok).map(|entry| entry.path())))
+    }
+
+    fn scan_dir(&self, path: &Path) -> io::Result<DirEntries> {
+        let entries = fs::read_dir(path)?;
+        Ok(Box::new(entries.filter_map(Result::ok).map(|entry| {
+            let path = entry.path();
+            // The file type comes with the directory entry, so only links cost an extra call.
+            let link = entry.file_type().is_ok_and(|file_type| file_type.is_symlink()).then(|| fs::read_link(&path).ok()).flatten();
+            DirEntry { metadata: LocalFs.metadata(&path).ok(), link, path }
+        })))
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+
+    fn open_seekable(&self, path: &Path) -> io::Result<Box<dyn ReadSeek>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::read_link(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_fs_reads_fixtures() {
+        let fs = LocalFs;
+        let metadata = fs.metadata(Path::new("tests/sample.jpg")).unwrap();
+        assert!(metadata.is_file());
+        assert!(fs.metadata(Path::new("tests/directory with spaces")).unwrap().is_dir());
+        assert!(fs
+            .read_dir(Path::new("tests"))
+            .unwrap()
+            .any(|path| path.ends_with("sample.gif")));
+        let entry = fs.scan_dir(Path::new("tests")).unwrap().find(|entry| entry.path.ends_with("sample.gif")).unwrap();
+        assert!(entry.metadata.unwrap().is_file());
+        assert!(entry.link.is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_local_fs_reports_links() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("target.txt"), "").unwrap();
+        std::os::unix::fs::symlink("target.txt", dir.path().join("good")).unwrap();
+        std::os::unix::fs::symlink("missing.txt", dir.path().join("broken")).unwrap();
+        let mut entries = LocalFs.scan_dir(dir.path()).unwrap().collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        let summary = entries
+            .iter()
+            .map(|entry| (entry.path.file_name().unwrap().to_str().unwrap(), entry.metadata.is_some(), entry.link.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("broken", false, Some(PathBuf::from("missing.txt"))),
+                ("good", true, Some(PathBuf::from("target.txt"))),
+                ("target.txt", true, None),
+            ]
+        );
+        assert!(LocalFs.read_link(&dir.path().join("target.txt")).is_err());
+    }
+}
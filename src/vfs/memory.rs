@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+use std::io::{self, Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use super::{FileSystem, FileType, Metadata, ReadSeek};
+
+#[derive(Debug, Clone)]
+enum Node {
+    File { contents: Arc<[u8]>, modified: SystemTime },
+    Dir { modified: SystemTime },
+}
+
+impl Node {
+    fn is_dir(&self) -> bool {
+        matches!(self, Node::Dir { .. })
+    }
+}
+
+/// Keeps a whole tree in memory, which lets tests run without touching disk.
+///
+/// Parent directories are created implicitly when files are added.
+#[derive(Debug, Default)]
+pub struct MemoryFs {
+    nodes: RwLock<BTreeMap<PathBuf, Node>>,
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_dir(&self, path: impl AsRef<Path>) {
+        let mut nodes = self.nodes.write().unwrap();
+        for ancestor in path.as_ref().ancestors() {
+            nodes
+                .entry(ancestor.to_path_buf())
+                .or_insert(Node::Dir { modified: SystemTime::now() });
+        }
+    }
+
+    pub fn add_file(&self, path: impl AsRef<Path>, contents: impl Into<Vec<u8>>) {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            self.add_dir(parent);
+        }
+        self.nodes.write().unwrap().insert(
+            path.to_path_buf(),
+            Node::File { contents: contents.into().into(), modified: SystemTime::now() },
+        );
+    }
+
+    fn node(&self, path: &Path) -> io::Result<Node> {
+        self.nodes
+            .read()
+            .unwrap()
+            .get(path)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} not found", path.display())))
+    }
+}
+
+impl FileSystem for MemoryFs {
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        Ok(match self.node(path)? {
+            Node::File { contents, modified } => Metadata {
+                file_type: FileType::File,
+                len: contents.len() as u64,
+                modified: Some(modified),
+                permissions: None,
+            },
+            Node::Dir { modified } => {
+                Metadata { file_type: FileType::Dir, len: 0, modified: Some(modified), permissions: None }
+            }
+        })
+    }
+
+    fn read_dir<'a>(&'a self, path: &Path) -> io::Result<Box<dyn Iterator<Item = PathBuf> + 'a>> {
+        if !self.node(path)?.is_dir() {
+            return Err(io::Error::new(io::ErrorKind::NotADirectory, format!("{} is not a directory", path.display())));
+        }
+        let children = self
+            .nodes
+            .read()
+            .unwrap()
+            .keys()
+            .filter(|candidate| candidate.parent() == Some(path))
+            .cloned()
+            .collect::<Vec<_>>();
+        Ok(Box::new(children.into_iter()))
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        Ok(self.open_seekable(path)?)
+    }
+
+    fn open_seekable(&self, path: &Path) -> io::Result<Box<dyn ReadSeek>> {
+        match self.node(path)? {
+            Node::File { contents, .. } => Ok(Box::new(Cursor::new(contents))),
+            Node::Dir { .. } => Err(io::Error::new(io::ErrorKind::IsADirectory, format!("{} is a directory", path.display()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_fs_creates_parents() {
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/a/b.txt", "hello");
+        assert!(fs.metadata(Path::new("/srv")).unwrap().is_dir());
+        assert!(fs.metadata(Path::new("/srv/a")).unwrap().is_dir());
+        let metadata = fs.metadata(Path::new("/srv/a/b.txt")).unwrap();
+        assert!(metadata.is_file());
+        assert_eq!(metadata.len, 5);
+    }
+
+    #[test]
+    fn test_memory_fs_read_dir_lists_direct_children_only() {
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/a/b.txt", "");
+        fs.add_file("/srv/c.txt", "");
+        let mut children = fs.read_dir(Path::new("/srv")).unwrap().collect::<Vec<_>>();
+        children.sort();
+        assert_eq!(children, vec![PathBuf::from("/srv/a"), PathBuf::from("/srv/c.txt")]);
+        assert!(fs.read_dir(Path::new("/srv/c.txt")).is_err());
+    }
+
+    #[test]
+    fn test_memory_fs_open() {
+        let fs = MemoryFs::new();
+        fs.add_file("/srv/b.txt", "contents");
+        let mut buffer = String::new();
+        fs.open(Path::new("/srv/b.txt")).unwrap().read_to_string(&mut buffer).unwrap();
+        assert_eq!(buffer, "contents");
+        assert_eq!(fs.open(Path::new("/srv/missing")).err().map(|e| e.kind()), Some(io::ErrorKind::NotFound));
+        assert!(fs.open(Path::new("/srv")).is_err());
+    }
+}
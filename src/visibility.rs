@@ -0,0 +1,205 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::vfs::FileSystem;
+
+/// The ignore file read in every directory, in `.gitignore` syntax.
+pub const IGNORE_FILE: &str = ".rweignore";
+
+/// Ignore files larger than this are not read.
+const IGNORE_FILE_LIMIT: u64 = 1024 * 1024;
+
+/// Which entries are kept out of listings, searches, feeds and downloads,
+/// and answered with 404 when asked for directly.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Visibility {
+    /// Whether names starting with '.' are shown like any other.
+    pub show_hidden: bool,
+    /// Whether `.gitignore` files are obeyed as well as `.rweignore` files.
+    pub use_gitignore: bool,
+}
+
+impl Visibility {
+    /// The rules for the entries of the directory `tail` below `root`,
+    /// gathered from the ignore files `fs` holds in every directory on the
+    /// way down.
+    pub fn rules(self, fs: &dyn FileSystem, root: &Path, tail: &str) -> Rules {
+        let mut rules = Rules::new(self, fs, root);
+        for segment in tail.split('/').filter(|segment| !segment.is_empty()) {
+            rules = rules.descend(fs, segment);
+        }
+        rules
+    }
+
+    /// Whether `tail`, or any directory on the way to it, is hidden. Tails
+    /// that step up with `..` are left for path resolution to refuse.
+    pub fn hides(self, fs: &dyn FileSystem, root: &Path, tail: &str) -> bool {
+        let segments = tail.split('/').filter(|segment| !matches!(*segment, "" | ".")).collect::<Vec<_>>();
+        if segments.contains(&"..") {
+            return false;
+        }
+        let mut rules = Rules::new(self, fs, root);
+        for (index, segment) in segments.iter().enumerate() {
+            let is_dir = index + 1 < segments.len()
+                || fs.metadata(&rules.dir.join(segment)).is_ok_and(|metadata| metadata.is_dir());
+            if rules.hides(segment, is_dir) {
+                return true;
+            }
+            rules = rules.descend(fs, segment);
+        }
+        false
+    }
+}
+
+/// What is hidden inside one directory. Rules are cheap to clone, so a walk
+/// can keep one per directory it has yet to enter.
+#[derive(Debug, Clone)]
+pub struct Rules {
+    visibility: Visibility,
+    /// The directory as reached from the served root, without resolving
+    /// symlinks, which is what ignore patterns are matched against.
+    dir: PathBuf,
+    /// The ignore files from the served root down to `dir`, outermost first.
+    ignores: Vec<Arc<Gitignore>>,
+}
+
+impl Rules {
+    fn new(visibility: Visibility, fs: &dyn FileSystem, root: &Path) -> Rules {
+        let mut rules = Rules { visibility, dir: root.to_path_buf(), ignores: Vec::new() };
+        rules.load(fs);
+        rules
+    }
+
+    /// Whether the entry `name` of this directory is left out. Ignore files
+    /// further down win over those above them, as with git.
+    pub fn hides(&self, name: &str, is_dir: bool) -> bool {
+        if !self.visibility.show_hidden && name.starts_with('.') {
+            return true;
+        }
+        let path = self.dir.join(name);
+        for ignore in self.ignores.iter().rev() {
+            match ignore.matched(&path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
+
+    /// The rules inside the subdirectory `name`.
+    pub fn descend(&self, fs: &dyn FileSystem, name: &str) -> Rules {
+        let mut rules = Rules { dir: self.dir.join(name), ..self.clone() };
+        rules.load(fs);
+        rules
+    }
+
+    /// Adds the ignore files of `dir`; `.rweignore` comes last so it can
+    /// override `.gitignore`.
+    fn load(&mut self, fs: &dyn FileSystem) {
+        let mut builder = GitignoreBuilder::new(&self.dir);
+        let mut found = false;
+        let names = if self.visibility.use_gitignore { &[".gitignore", IGNORE_FILE][..] } else { &[IGNORE_FILE][..] };
+        for name in names {
+            let path = self.dir.join(name);
+            if !fs.metadata(&path).is_ok_and(|metadata| metadata.is_file()) {
+                continue;
+            }
+            let mut contents = String::new();
+            let Ok(file) = fs.open(&path) else { continue };
+            if file.take(IGNORE_FILE_LIMIT).read_to_string(&mut contents).is_err() {
+                continue;
+            }
+            for line in contents.lines() {
+                // Lines that are not valid patterns are skipped; the rest still apply.
+                let _ = builder.add_line(Some(path.clone()), line);
+            }
+            found = true;
+        }
+        if let Some(ignore) = found.then(|| builder.build().ok()).flatten() {
+            self.ignores.push(Arc::new(ignore));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::vfs::{LocalFs, MemoryFs};
+
+    fn tree() -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("docs/drafts")).unwrap();
+        fs::create_dir_all(root.path().join("build")).unwrap();
+        fs::write(root.path().join(IGNORE_FILE), "*.log\nbuild/\n").unwrap();
+        fs::write(root.path().join("docs").join(IGNORE_FILE), "!keep.log\n/drafts\n").unwrap();
+        fs::write(root.path().join(".gitignore"), "*.tmp\n").unwrap();
+        root
+    }
+
+    #[test]
+    fn test_dotfiles_are_hidden_unless_shown() {
+        let root = tree();
+        let rules = Visibility::default().rules(&LocalFs, root.path(), "");
+        assert!(rules.hides(".env", false));
+        assert!(rules.hides(".git", true));
+        assert!(!rules.hides("notes.txt", false));
+        let rules = Visibility { show_hidden: true, ..Visibility::default() }.rules(&LocalFs, root.path(), "");
+        assert!(!rules.hides(".env", false));
+    }
+
+    #[test]
+    fn test_ignore_files_apply_to_their_subtree() {
+        let root = tree();
+        let rules = Visibility::default().rules(&LocalFs, root.path(), "");
+        assert!(rules.hides("app.log", false));
+        assert!(rules.hides("build", true));
+        assert!(!rules.hides("build", false));
+        assert!(!rules.hides("drafts", true));
+        assert!(!rules.hides("scratch.tmp", false));
+
+        let docs = rules.descend(&LocalFs, "docs");
+        assert!(docs.hides("other.log", false));
+        assert!(!docs.hides("keep.log", false));
+        assert!(docs.hides("drafts", true));
+    }
+
+    #[test]
+    fn test_gitignore_is_optional() {
+        let root = tree();
+        let rules = Visibility { use_gitignore: true, ..Visibility::default() }.rules(&LocalFs, root.path(), "docs/");
+        assert!(rules.hides("scratch.tmp", false));
+        assert!(!rules.hides("keep.log", false));
+    }
+
+    #[test]
+    fn test_hides_checks_every_segment() {
+        let root = tree();
+        let visibility = Visibility::default();
+        assert!(visibility.hides(&LocalFs, root.path(), "build/out.bin"));
+        assert!(visibility.hides(&LocalFs, root.path(), "docs/drafts/"));
+        assert!(visibility.hides(&LocalFs, root.path(), ".rweignore"));
+        assert!(visibility.hides(&LocalFs, root.path(), "docs/.secret/readme.txt"));
+        assert!(!visibility.hides(&LocalFs, root.path(), "docs/keep.log"));
+        assert!(!visibility.hides(&LocalFs, root.path(), "docs/"));
+        assert!(!visibility.hides(&LocalFs, root.path(), ""));
+        assert!(!visibility.hides(&LocalFs, root.path(), "./docs/../notes.txt"));
+    }
+
+    #[test]
+    fn test_ignore_files_are_read_through_the_file_system() {
+        let fs = MemoryFs::new();
+        fs.add_file(Path::new("/srv").join(IGNORE_FILE), "*.log\n");
+        fs.add_file("/srv/logs/app.log", "");
+        let visibility = Visibility::default();
+        assert!(visibility.rules(&fs, Path::new("/srv"), "logs/").hides("app.log", false));
+        assert!(visibility.hides(&fs, Path::new("/srv"), "logs/app.log"));
+        assert!(!visibility.hides(&fs, Path::new("/srv"), "logs/"));
+    }
+}
@@ -0,0 +1,129 @@
+//! A real server on an ephemeral port plus a minimal HTTP client, for
+//! exercising the explorer over actual sockets.
+
+#![allow(dead_code)]
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rust_web_explorer::{App, Config};
+
+/// A server running on a background thread for the rest of the test process.
+pub struct TestServer {
+    addr: SocketAddr,
+    root: PathBuf,
+    // Keeps a temporary root alive for as long as the server uses it.
+    _temp_root: Option<tempfile::TempDir>,
+}
+
+impl TestServer {
+    /// Serves `root` with otherwise default settings.
+    pub fn start(root: &Path) -> TestServer {
+        Self::start_with(Config { root: Some(root.to_path_buf()), ..Config::default() })
+    }
+
+    /// Serves a fresh, empty temporary directory, which `root()` returns for
+    /// the test to populate.
+    pub fn start_temp() -> TestServer {
+        let temp_root = tempfile::tempdir().expect("failed to create a temporary root");
+        let mut server = Self::start(temp_root.path());
+        server._temp_root = Some(temp_root);
+        server
+    }
+
+    /// Serves the repository's own `tests/` fixtures.
+    pub fn start_fixtures() -> TestServer {
+        Self::start(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests"))
+    }
+
+    pub fn start_with(config: Config) -> TestServer {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind an ephemeral port");
+        let addr = listener.local_addr().unwrap();
+        let root = config.root.clone().unwrap_or_else(|| std::env::current_dir().unwrap());
+        let app = App::new(config).expect("failed to build the app");
+        std::thread::spawn(move || rust_web_explorer::serve(Arc::new(app), listener));
+        TestServer { addr, root, _temp_root: None }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn get(&self, target: &str) -> TestResponse {
+        self.request(&format!("GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", target, self.addr))
+    }
+
+    /// Posts `form`, already URL-encoded, as a form submission.
+    pub fn post(&self, target: &str, form: &str) -> TestResponse {
+        self.request(&format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            target,
+            self.addr,
+            form.len(),
+            form
+        ))
+    }
+
+    /// Sends `raw` verbatim and reads the response until the server closes
+    /// the connection.
+    pub fn request(&self, raw: &str) -> TestResponse {
+        let mut stream = TcpStream::connect(self.addr).expect("failed to connect to the test server");
+        stream.write_all(raw.as_bytes()).unwrap();
+        let mut bytes = Vec::new();
+        stream.read_to_end(&mut bytes).unwrap();
+        TestResponse::parse(&bytes)
+    }
+}
+
+/// A parsed response.
+#[derive(Debug)]
+pub struct TestResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl TestResponse {
+    fn parse(bytes: &[u8]) -> TestResponse {
+        let split = bytes.windows(4).position(|window| window == b"\r\n\r\n").expect("response has no header terminator");
+        let head = String::from_utf8_lossy(&bytes[..split]);
+        let mut lines = head.split("\r\n");
+        let status_line = lines.next().unwrap();
+        let status = status_line.split(' ').nth(1).and_then(|code| code.parse().ok()).expect("malformed status line");
+        let headers = lines
+            .filter_map(|line| line.split_once(": "))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let mut response = TestResponse { status, headers, body: bytes[split + 4..].to_vec() };
+        if response.header("Transfer-Encoding") == Some("chunked") {
+            response.body = decode_chunked(&response.body);
+        }
+        response
+    }
+
+    /// Returns the first header called `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8(self.body.clone()).expect("body is not UTF-8")
+    }
+}
+
+fn decode_chunked(mut bytes: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = bytes.windows(2).position(|window| window == b"\r\n").expect("chunk size line is not terminated");
+        let size = std::str::from_utf8(&bytes[..line_end]).ok().and_then(|size| usize::from_str_radix(size, 16).ok());
+        let size = size.expect("malformed chunk size");
+        if size == 0 {
+            return body;
+        }
+        let start = line_end + 2;
+        body.extend_from_slice(&bytes[start..start + size]);
+        bytes = &bytes[start + size + 2..];
+    }
+}
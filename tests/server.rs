@@ -0,0 +1,746 @@
+mod common;
+
+use std::fs;
+
+use common::TestServer;
+use rust_web_explorer::{Config, SymlinkPolicy};
+
+#[test]
+fn test_root_listing_links_every_entry() {
+    let server = TestServer::start_temp();
+    fs::create_dir(server.root().join("docs")).unwrap();
+    fs::write(server.root().join("notes & todo.txt"), "remember").unwrap();
+
+    let response = server.get("/");
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Content-Type"), Some("text/html; charset=utf-8"));
+    let body = response.text();
+    assert!(body.contains("<a href=\"/docs/\">docs</a>"));
+    assert!(body.contains("notes &amp; todo.txt"));
+    assert!(body.contains("<a href=\"/notes%20%26%20todo.txt\" download>Download</a>"));
+    assert!(!body.contains("Parent Directory"));
+}
+
+#[test]
+fn test_text_file_is_served_verbatim() {
+    let server = TestServer::start_temp();
+    fs::write(server.root().join("notes.txt"), "line one\nline two\n").unwrap();
+
+    let response = server.get("/notes.txt");
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Content-Type"), Some("text/plain"));
+    assert_eq!(response.header("Content-Length"), Some("18"));
+    assert_eq!(response.text(), "line one\nline two\n");
+}
+
+#[test]
+fn test_binary_downloads_are_byte_for_byte() {
+    let server = TestServer::start_fixtures();
+    for (name, mime_type) in [("sample.jpg", "image/jpeg"), ("sample.gif", "image/gif"), ("sample.mp4", "video/mp4")] {
+        let expected = fs::read(server.root().join(name)).unwrap();
+        let response = server.get(&format!("/{}", name));
+        assert_eq!(response.status, 200, "{}", name);
+        assert_eq!(response.header("Content-Type"), Some(mime_type));
+        assert_eq!(response.header("Content-Length"), Some(expected.len().to_string().as_str()));
+        assert!(response.body == expected, "{} differs from the file on disk", name);
+    }
+}
+
+#[test]
+fn test_traversal_is_rejected() {
+    let outer = tempfile::tempdir().unwrap();
+    fs::write(outer.path().join("secret.txt"), "top secret").unwrap();
+    fs::create_dir(outer.path().join("public")).unwrap();
+    let server = TestServer::start(&outer.path().join("public"));
+
+    for target in ["/../secret.txt", "/..%2Fsecret.txt", "/%2E%2E/secret.txt", "/docs/../../secret.txt"] {
+        let response = server.get(target);
+        assert_eq!(response.status, 400, "{} was not rejected", target);
+        assert!(!response.text().contains("top secret"));
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlinks_out_of_the_root_are_rejected() {
+    let outer = tempfile::tempdir().unwrap();
+    fs::write(outer.path().join("secret.txt"), "top secret").unwrap();
+    fs::create_dir(outer.path().join("public")).unwrap();
+    std::os::unix::fs::symlink(outer.path().join("secret.txt"), outer.path().join("public/link.txt")).unwrap();
+    let server = TestServer::start(&outer.path().join("public"));
+
+    let response = server.get("/link.txt");
+    assert_eq!(response.status, 403);
+    let body = response.text();
+    assert!(!body.contains("top secret"));
+    assert!(body.contains("&#x2f;link.txt is a symbolic link to"));
+    assert!(body.contains("which is outside the served directory"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlink_policies() {
+    let outer = tempfile::tempdir().unwrap();
+    fs::write(outer.path().join("secret.txt"), "top secret").unwrap();
+    fs::create_dir(outer.path().join("public")).unwrap();
+    fs::write(outer.path().join("public/v2.txt"), "second").unwrap();
+    std::os::unix::fs::symlink(outer.path().join("secret.txt"), outer.path().join("public/outside.txt")).unwrap();
+    std::os::unix::fs::symlink("v2.txt", outer.path().join("public/latest.txt")).unwrap();
+    let start = |symlinks| {
+        TestServer::start_with(Config { root: Some(outer.path().join("public")), symlinks, ..Config::default() })
+    };
+
+    let anywhere = start(SymlinkPolicy::Anywhere);
+    assert_eq!(anywhere.get("/outside.txt").text(), "top secret");
+    assert_eq!(anywhere.get("/latest.txt").text(), "second");
+
+    let never = start(SymlinkPolicy::Never);
+    let response = never.get("/latest.txt");
+    assert_eq!(response.status, 403);
+    assert!(response.text().contains("&#x2f;latest.txt is a symbolic link, and this server does not follow symbolic links"));
+    assert_eq!(never.get("/v2.txt").text(), "second");
+    assert!(never.get("/").text().contains("latest.txt <span class=\"link-target\">&rarr; v2.txt</span>"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_broken_symlinks_are_marked() {
+    let server = TestServer::start_temp();
+    std::os::unix::fs::symlink("v2", server.root().join("latest")).unwrap();
+
+    let listing = server.get("/");
+    let body = listing.text();
+    assert!(body.contains("<tr class=\"broken-link\"><td class=\"select\"></td><td>&#128279; latest <span class=\"link-target\">&rarr; v2</span> <em>(broken link)</em></td>"));
+    assert!(!body.contains("<a href=\"/latest\" download>"));
+
+    let json = server.get("/?format=json").text();
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(json["entries"][0]["link_target"], "v2");
+    assert_eq!(json["entries"][0]["broken_link"], true);
+
+    let response = server.get("/latest");
+    assert_eq!(response.status, 404);
+    assert!(response.text().contains("&#x2f;latest is a symbolic link to v2, which does not exist"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_hidden_path_keeps_link_targets_private() {
+    let outer = tempfile::tempdir().unwrap();
+    fs::write(outer.path().join("secret.txt"), "top secret").unwrap();
+    fs::create_dir_all(outer.path().join("public/docs")).unwrap();
+    fs::write(outer.path().join("public/v2.txt"), "second").unwrap();
+    std::os::unix::fs::symlink(outer.path().join("secret.txt"), outer.path().join("public/outside.txt")).unwrap();
+    std::os::unix::fs::symlink(outer.path().join("public/v2.txt"), outer.path().join("public/docs/latest.txt")).unwrap();
+    std::os::unix::fs::symlink(outer.path().join("gone"), outer.path().join("public/gone")).unwrap();
+    let config = Config { root: Some(outer.path().join("public")), hide_path: true, ..Config::default() };
+    let server = TestServer::start_with(config);
+    let outer_path = outer.path().display().to_string();
+
+    let listing = server.get("/").text();
+    assert!(!listing.contains(&outer_path) && !listing.contains("&rarr;"));
+    assert!(listing.contains("outside.txt</td>"));
+    let json = server.get("/?format=json").text();
+    assert!(!json.contains(&outer_path));
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert!(json["entries"].as_array().unwrap().iter().all(|entry| entry["link_target"].is_null()));
+    assert!(server.get("/docs/").text().contains("latest.txt <span class=\"link-target\">&rarr; &#x2f;v2.txt</span>"));
+
+    for (target, message) in [
+        ("/outside.txt", "&#x2f;outside.txt is a symbolic link to somewhere outside the served directory"),
+        ("/gone", "&#x2f;gone is a symbolic link to something outside the served directory that does not exist"),
+    ] {
+        let body = server.get(target).text();
+        assert!(body.contains(message), "{}: {}", target, body);
+        assert!(!body.contains(&outer_path));
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlinked_root() {
+    let outer = tempfile::tempdir().unwrap();
+    fs::create_dir_all(outer.path().join("real/docs")).unwrap();
+    fs::write(outer.path().join("real/v2.txt"), "second").unwrap();
+    std::os::unix::fs::symlink(outer.path().join("real/v2.txt"), outer.path().join("real/docs/latest.txt")).unwrap();
+    std::os::unix::fs::symlink(outer.path().join("real"), outer.path().join("served")).unwrap();
+    let server = TestServer::start(&outer.path().join("served"));
+
+    assert!(!server.get("/").text().contains("Parent Directory"));
+    assert_eq!(server.get("/docs/latest.txt").text(), "second");
+
+    // Targets are shown from the root even though they name it by its real path.
+    let config = Config { root: Some(outer.path().join("served")), hide_path: true, ..Config::default() };
+    let server = TestServer::start_with(config);
+    assert!(server.get("/docs/").text().contains("latest.txt <span class=\"link-target\">&rarr; &#x2f;v2.txt</span>"));
+}
+
+#[test]
+fn test_directory_with_spaces_fixture() {
+    let server = TestServer::start_fixtures();
+
+    let listing = server.get("/directory%20with%20spaces/");
+    assert_eq!(listing.status, 200);
+    let body = listing.text();
+    assert!(body.contains("file with spaces.txt"));
+    assert!(body.contains("<a href=\"/directory%20with%20spaces%2Ffile%20with%20spaces.txt\" download>Download</a>"));
+    assert!(body.contains("<a href=\"/\">Parent Directory</a>"));
+
+    let file = server.get("/directory%20with%20spaces%2Ffile%20with%20spaces.txt");
+    assert_eq!(file.status, 200);
+    assert_eq!(file.text(), fs::read_to_string(server.root().join("directory with spaces/file with spaces.txt")).unwrap());
+
+    let same_file = server.get("/directory%20with%20spaces/file%20with%20spaces.txt");
+    assert_eq!(same_file.body, file.body);
+}
+
+#[test]
+fn test_directory_with_spaces_is_redirected_to_canonical_urls() {
+    let server = TestServer::start_fixtures();
+
+    for (target, location) in [
+        ("/directory%20with%20spaces", "/directory%20with%20spaces/"),
+        ("/directory%20with%20spaces?sort=size&order=desc", "/directory%20with%20spaces/?sort=size&order=desc"),
+        ("/directory%20with%20spaces%2F", "/directory%20with%20spaces/"),
+        ("/directory%20with%20spaces%2Ffile%20with%20spaces.txt/", "/directory%20with%20spaces%2Ffile%20with%20spaces.txt"),
+        ("/directory%20with%20spaces/file%20with%20spaces.txt/?view", "/directory%20with%20spaces/file%20with%20spaces.txt?view"),
+    ] {
+        let response = server.get(target);
+        assert_eq!(response.status, 301, "{} was not redirected", target);
+        assert_eq!(response.header("Location"), Some(location), "{} was redirected elsewhere", target);
+    }
+
+    assert_eq!(server.get("/directory%20with%20spaces/").status, 200);
+    assert_eq!(server.get("/directory%20with%20spaces%2Ffile%20with%20spaces.txt").status, 200);
+    let root = server.get("/").text();
+    assert!(root.contains("<a href=\"/directory%20with%20spaces/\">directory with spaces</a>"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_search_stays_inside_the_root() {
+    let outer = tempfile::tempdir().unwrap();
+    fs::create_dir_all(outer.path().join("private")).unwrap();
+    fs::write(outer.path().join("private/secret.txt"), "top secret").unwrap();
+    fs::create_dir_all(outer.path().join("public/reports/2024")).unwrap();
+    fs::write(outer.path().join("public/reports/2024/q1.txt"), "").unwrap();
+    std::os::unix::fs::symlink(outer.path().join("private"), outer.path().join("public/private")).unwrap();
+    let server = TestServer::start(&outer.path().join("public"));
+
+    let response = server.get("/?search&filter=*.txt");
+    assert_eq!(response.status, 200);
+    let body = response.text();
+    assert!(body.contains("<a href=\"/reports%2F2024%2Fq1.txt\" download>Download</a>"));
+    assert!(!body.contains("secret.txt"));
+}
+
+#[test]
+fn test_grep_streams_matches() {
+    let server = TestServer::start_temp();
+    fs::create_dir(server.root().join("logs")).unwrap();
+    fs::write(server.root().join("logs/app.log"), "ok\nfailed: <timeout>\nok\n").unwrap();
+    fs::write(server.root().join("logs/core"), b"failed\0").unwrap();
+
+    let response = server.get("/logs?grep=failed");
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Content-Length"), None);
+    let body = response.text();
+    assert!(body.contains("<a href=\"/logs%2Fapp.log?view#L2\">2</a>"));
+    assert!(body.contains("<mark>failed</mark>: &lt;timeout&gt;"));
+    assert!(body.contains("1 match in 1 of 2 files; 1 binary, oversized or unreadable file skipped"));
+    assert!(body.trim_end().ends_with("</html>"));
+
+    let invalid = server.get("/logs?grep=%28&regex=on");
+    assert_eq!(invalid.status, 400);
+}
+
+#[test]
+fn test_json_listing_is_negotiated() {
+    let server = TestServer::start_fixtures();
+
+    let response = server.request("GET /directory%20with%20spaces/ HTTP/1.1\r\nAccept: application/json\r\nConnection: close\r\n\r\n");
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Content-Type"), Some("application/json"));
+    let body = response.text();
+    assert!(body.contains("\"schema\": \"rust-web-explorer/listing\""));
+    assert!(body.contains("\"url\": \"/directory%20with%20spaces%2Ffile%20with%20spaces.txt\""));
+
+    assert_eq!(server.get("/?format=json").header("Content-Type"), Some("application/json"));
+    assert_eq!(server.get("/?format=yaml").status, 400);
+}
+
+#[test]
+fn test_plain_listing_formats() {
+    let server = TestServer::start_fixtures();
+
+    let txt = server.request("GET /directory%20with%20spaces/?format=txt HTTP/1.1\r\nHost: files.example:8080\r\nConnection: close\r\n\r\n");
+    assert_eq!(txt.header("Content-Type"), Some("text/plain; charset=utf-8"));
+    assert_eq!(
+        txt.text(),
+        "http://files.example:8080/directory%20with%20spaces%2Ffile%20with%20spaces\n\
+         http://files.example:8080/directory%20with%20spaces%2Ffile%20with%20spaces.txt\n"
+    );
+
+    let csv = server.get("/directory%20with%20spaces/?format=csv");
+    assert_eq!(csv.header("Content-Type"), Some("text/csv; charset=utf-8"));
+    assert!(csv.text().starts_with("name,kind,size,modified,mime_type,url\r\nfile with spaces,file,3,"));
+
+    let autoindex = server.get("/?format=autoindex").text();
+    assert!(autoindex.contains("<h1>Index of /</h1>"));
+    assert!(autoindex.contains("<a href=\"/directory%20with%20spaces/\">directory with spaces/</a>"));
+    let nested = server.get("/directory%20with%20spaces/?format=autoindex").text();
+    assert!(nested.contains("<a href=\"/directory%20with%20spaces/file%20with%20spaces.txt\">"));
+}
+
+#[test]
+fn test_malformed_request_is_rejected() {
+    let server = TestServer::start_temp();
+    let response = server.request("NONSENSE\r\n\r\n");
+    assert_eq!(response.status, 400);
+}
+
+#[test]
+fn test_atom_feed() {
+    let server = TestServer::start_fixtures();
+    let response = server.request("GET /?format=atom&recursive HTTP/1.1\r\nHost: files.example\r\nConnection: close\r\n\r\n");
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Content-Type"), Some("application/atom+xml; charset=utf-8"));
+    let xml = response.text();
+    assert!(xml.starts_with("<?xml"));
+    assert!(xml.contains("<id>http://files.example/directory%20with%20spaces%2Ffile%20with%20spaces.txt</id>"));
+    assert!(xml.contains("<media:thumbnail url="));
+}
+
+#[test]
+fn test_podcast_feed_and_ranges() {
+    let server = TestServer::start_fixtures();
+    let xml = server.request("GET /?format=rss HTTP/1.1\r\nHost: files.example\r\nConnection: close\r\n\r\n").text();
+    let len = std::fs::metadata(server.root().join("sample.mp4")).unwrap().len();
+    assert!(xml.contains(&format!("<enclosure url=\"http://files.example/sample.mp4\" length=\"{}\" type=\"video/mp4\"/>", len)));
+    assert!(!xml.contains("sample.jpg"));
+
+    let response = server.request("GET /sample.mp4 HTTP/1.1\r\nRange: bytes=0-3\r\nConnection: close\r\n\r\n");
+    assert_eq!(response.status, 206);
+    assert_eq!(response.header("Content-Range"), Some(format!("bytes 0-3/{}", len).as_str()));
+    assert_eq!(response.body.len(), 4);
+}
+
+#[test]
+fn test_playlist_uses_host_header() {
+    let server = TestServer::start_fixtures();
+    let response = server.request("GET /?format=m3u8&recursive HTTP/1.1\r\nHost: media.local:8080\r\nConnection: close\r\n\r\n");
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Content-Type"), Some("audio/x-mpegurl; charset=utf-8"));
+    assert_eq!(response.text(), "#EXTM3U\n#PLAYLIST:/\n#EXTINF:-1,sample.mp4\nhttp://media.local:8080/sample.mp4\n");
+}
+
+#[test]
+fn test_large_directory_is_paged_and_chunked() {
+    let root = tempfile::tempdir().unwrap();
+    for i in 0..1200 {
+        fs::write(root.path().join(format!("file{:04}.txt", i)), "").unwrap();
+    }
+    let server = TestServer::start(root.path());
+
+    let response = server.get("/");
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Transfer-Encoding"), Some("chunked"));
+    assert_eq!(response.header("Content-Length"), None);
+    let body = response.text();
+    assert!(body.contains("1200 entries"));
+    assert!(body.contains("file0499.txt") && !body.contains("file0500.txt"));
+    assert!(body.contains("1&ndash;500 of 1200"));
+    assert!(body.contains("<a href=\"?sort=name&order=asc&offset=500\" rel=\"next\">"));
+    assert!(body.trim_end().ends_with("</html>"));
+
+    let last = server.get("/?offset=1000").text();
+    assert!(last.contains("file1199.txt") && !last.contains("file0999.txt"));
+    assert!(!last.contains("rel=\"next\""));
+
+    let unsorted = server.get("/?sort=none&limit=100").text();
+    assert_eq!(unsorted.matches("<td class=\"select\">").count(), 100);
+    assert!(unsorted.contains("<a href=\"?sort=none&order=asc&offset=100&limit=100\" rel=\"next\">"));
+}
+
+#[test]
+fn test_hidden_and_ignored_entries_are_not_served() {
+    let server = TestServer::start_temp();
+    fs::create_dir(server.root().join(".git")).unwrap();
+    fs::write(server.root().join(".git/config"), "[core]").unwrap();
+    fs::write(server.root().join(".env"), "SECRET=1").unwrap();
+    fs::write(server.root().join(".rweignore"), "*.key\nprivate/\n").unwrap();
+    fs::write(server.root().join("server.key"), "key").unwrap();
+    fs::create_dir(server.root().join("private")).unwrap();
+    fs::write(server.root().join("private/notes.txt"), "notes").unwrap();
+    fs::write(server.root().join("readme.txt"), "hello").unwrap();
+
+    let listing = server.get("/").text();
+    assert!(listing.contains("readme.txt"));
+    for hidden in [".git", ".env", ".rweignore", "server.key", "private"] {
+        assert!(!listing.contains(&format!(">{}<", hidden)), "{} was listed", hidden);
+    }
+    assert!(listing.contains("1 entries"));
+
+    assert!(server.get("/?format=txt").text().ends_with("/readme.txt\n"));
+    assert_eq!(server.get("/?format=txt").text().lines().count(), 1);
+    for target in ["/.env", "/.git/config", "/server.key", "/private", "/private%2Fnotes.txt"] {
+        assert_eq!(server.get(target).status, 404, "{} was served", target);
+    }
+    assert!(!server.get("/?search&q=notes").text().contains("notes.txt"));
+    #[cfg(unix)]
+    {
+        // Links to hidden entries do not serve them under another name.
+        std::os::unix::fs::symlink(".git", server.root().join("cfg")).unwrap();
+        std::os::unix::fs::symlink(".env", server.root().join("env")).unwrap();
+        std::os::unix::fs::symlink("private/notes.txt", server.root().join("notes")).unwrap();
+        for target in ["/cfg/", "/cfg/config", "/env", "/notes"] {
+            assert_eq!(server.get(target).status, 404, "{} was served", target);
+        }
+        assert_eq!(server.post("/", "archive=zip&path=cfg").status, 404);
+    }
+}
+
+#[test]
+fn test_ignore_files_inside_archives_are_honoured() {
+    use std::io::Write;
+
+    let server = TestServer::start_temp();
+    let mut zip = zip::ZipWriter::new(fs::File::create(server.root().join("build.zip")).unwrap());
+    for (name, contents) in [(".rweignore", "*.key\n"), ("notes.txt", "notes"), ("server.key", "key")] {
+        zip.start_file(name, zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(contents.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap();
+
+    let listing = server.get("/build.zip/").text();
+    assert!(listing.contains("value=\"notes.txt\""));
+    assert!(!listing.contains("server.key"));
+    assert_eq!(server.get("/build.zip%2Fnotes.txt").text(), "notes");
+    assert_eq!(server.get("/build.zip%2Fserver.key").status, 404);
+}
+
+#[test]
+fn test_non_ascii_names_are_linked_as_utf8() {
+    let server = TestServer::start_temp();
+    fs::create_dir_all(server.root().join("日本/café")).unwrap();
+    fs::write(server.root().join("日本/café/menu ü.txt"), "crêpes").unwrap();
+
+    let root = server.get("/").text();
+    assert!(root.contains("<a href=\"/%E6%97%A5%E6%9C%AC/\">日本</a>"));
+    let listing = server.get("/%E6%97%A5%E6%9C%AC/caf%C3%A9/");
+    assert_eq!(listing.status, 200);
+    let body = listing.text();
+    assert!(body.contains("<a href=\"/%E6%97%A5%E6%9C%AC/\">日本</a>"));
+    assert!(body.contains("href=\"/%E6%97%A5%E6%9C%AC%2Fcaf%C3%A9%2Fmenu%20%C3%BC.txt\""));
+    assert_eq!(server.get("/%E6%97%A5%E6%9C%AC%2Fcaf%C3%A9%2Fmenu%20%C3%BC.txt").text(), "crêpes");
+}
+
+#[test]
+fn test_static_site_mode() {
+    let site = tempfile::tempdir().unwrap();
+    fs::create_dir_all(site.path().join("guide/setup")).unwrap();
+    fs::create_dir(site.path().join("assets")).unwrap();
+    fs::write(site.path().join("index.html"), "<h1>Home</h1>").unwrap();
+    fs::write(site.path().join("guide/home.html"), "<h1>Guide</h1>").unwrap();
+    fs::write(site.path().join("guide/setup/index.html"), "<h1>Setup</h1>").unwrap();
+    fs::write(site.path().join("assets/style.css"), "body {}").unwrap();
+    let config = Config {
+        root: Some(site.path().to_path_buf()),
+        static_site: true,
+        index_files: vec!["home.html".to_string(), "index.html".to_string()],
+        ..Config::default()
+    };
+    let server = TestServer::start_with(config);
+
+    let home = server.get("/");
+    assert_eq!(home.status, 200);
+    assert_eq!(home.header("Content-Type"), Some("text/html; charset=utf-8"));
+    assert_eq!(home.text(), "<h1>Home</h1>");
+    assert_eq!(server.get("/guide/").text(), "<h1>Guide</h1>");
+    assert_eq!(server.get("/guide/setup/").text(), "<h1>Setup</h1>");
+    assert_eq!(server.get("/gu%69de/").text(), "<h1>Guide</h1>");
+    assert_eq!(server.get("/assets/style.css").text(), "body {}");
+
+    for (target, location) in [("/guide", "/guide/"), ("/guide%2Fsetup", "/guide/setup/"), ("/guide/setup?lang=en", "/guide/setup/?lang=en")] {
+        let response = server.get(target);
+        assert_eq!(response.status, 301, "{} was not redirected", target);
+        assert_eq!(response.header("Location"), Some(location));
+    }
+
+    let listing = server.get("/assets/");
+    assert_eq!(listing.status, 200);
+    assert!(listing.text().contains("style.css"));
+    assert!(server.get("/?format=json").text().contains("\"name\": \"guide\""));
+    assert_eq!(server.get("/missing/page").status, 400);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_static_site_index_files_stay_inside_the_root() {
+    let outer = tempfile::tempdir().unwrap();
+    fs::write(outer.path().join("secret.html"), "top secret").unwrap();
+    fs::create_dir_all(outer.path().join("site/docs")).unwrap();
+    fs::write(outer.path().join("site/home.html"), "<h1>Home</h1>").unwrap();
+    std::os::unix::fs::symlink(outer.path().join("secret.html"), outer.path().join("site/index.html")).unwrap();
+    std::os::unix::fs::symlink("../home.html", outer.path().join("site/docs/index.html")).unwrap();
+    let config = Config { root: Some(outer.path().join("site")), static_site: true, ..Config::default() };
+    let server = TestServer::start_with(config);
+
+    let response = server.get("/");
+    assert_eq!(response.status, 403);
+    let body = response.text();
+    assert!(!body.contains("top secret"));
+    assert!(body.contains("&#x2f;index.html is a symbolic link to"));
+    assert_eq!(server.get("/docs/").text(), "<h1>Home</h1>");
+}
+
+#[test]
+fn test_spa_fallback() {
+    let site = tempfile::tempdir().unwrap();
+    fs::write(site.path().join("app.html"), "<div id=app></div>").unwrap();
+    fs::write(site.path().join("main.js"), "render()").unwrap();
+    let config = Config { root: Some(site.path().to_path_buf()), spa_fallback: Some("app.html".into()), ..Config::default() };
+    let server = TestServer::start_with(config);
+
+    for target in ["/users/42", "/settings/", "/missing.js"] {
+        let response = server.get(target);
+        assert_eq!(response.status, 200, "{} did not fall back", target);
+        assert_eq!(response.text(), "<div id=app></div>");
+    }
+    assert_eq!(server.get("/main.js").text(), "render()");
+    assert_eq!(server.get("/.env").status, 404);
+    for target in ["/../x", "/users%2F..%2F..%2Fx"] {
+        assert_eq!(server.get(target).status, 400, "{} fell back", target);
+    }
+
+    let missing = Config { root: Some(site.path().to_path_buf()), spa_fallback: Some("nope.html".into()), ..Config::default() };
+    assert!(rust_web_explorer::App::new(missing).is_err());
+}
+
+#[test]
+fn test_readme_is_rendered_below_the_listing() {
+    let server = TestServer::start_temp();
+    fs::create_dir_all(server.root().join("my project/img")).unwrap();
+    fs::write(server.root().join("my project/README.md"), "# Hello\n\n![logo](img/logo.png) [home](../)\n\n<script>alert(1)</script>\n").unwrap();
+    fs::create_dir(server.root().join("notes")).unwrap();
+    fs::write(server.root().join("notes/README.txt"), "a <b> c").unwrap();
+    fs::create_dir(server.root().join("private")).unwrap();
+    fs::write(server.root().join("private/README.md"), "secret").unwrap();
+    fs::write(server.root().join("private/.rweignore"), "README.md\n").unwrap();
+
+    let body = server.get("/my%20project/").text();
+    let table_end = body.find("</tbody></table>").unwrap();
+    let readme = body.find("<section class=\"readme\">").expect("README missing");
+    assert!(readme > table_end);
+    assert!(body.contains("<h1>Hello</h1>"));
+    assert!(body.contains("<img src=\"/my%20project/img/logo.png\" alt=\"logo\">"));
+    assert!(body.contains("href=\"/\""));
+    assert!(!body.contains("alert(1)"));
+
+    assert!(server.get("/notes/").text().contains("<pre>a &lt;b&gt; c</pre>"));
+    assert!(!server.get("/private/").text().contains("secret"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_readme_symlinks_stay_inside_the_root() {
+    let outer = tempfile::tempdir().unwrap();
+    fs::write(outer.path().join("secret.txt"), "top secret").unwrap();
+    fs::create_dir_all(outer.path().join("public/docs")).unwrap();
+    fs::write(outer.path().join("public/notes.txt"), "shared notes").unwrap();
+    std::os::unix::fs::symlink(outer.path().join("secret.txt"), outer.path().join("public/README.txt")).unwrap();
+    std::os::unix::fs::symlink("../notes.txt", outer.path().join("public/docs/README.txt")).unwrap();
+    let server = TestServer::start(&outer.path().join("public"));
+
+    let listing = server.get("/").text();
+    assert!(!listing.contains("top secret"));
+    assert!(!listing.contains("<section class=\"readme\">"));
+    assert!(server.get("/docs/").text().contains("<pre>shared notes</pre>"));
+}
+
+#[test]
+fn test_directory_downloads_as_zip() {
+    let server = TestServer::start_temp();
+    fs::create_dir_all(server.root().join("project/src")).unwrap();
+    fs::create_dir(server.root().join("project/empty")).unwrap();
+    fs::write(server.root().join("project/src/main.rs"), "fn main() {}\n").unwrap();
+    fs::write(server.root().join("project/build.log"), "noise").unwrap();
+    fs::write(server.root().join("project/.env"), "SECRET=1").unwrap();
+    fs::write(server.root().join("project/.rweignore"), "*.log\n").unwrap();
+    let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+    fs::File::options().write(true).open(server.root().join("project/src/main.rs")).unwrap().set_modified(modified).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(server.root().join("project/src/main.rs"), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    let listing = server.get("/").text();
+    assert!(listing.contains("<a href=\"/project/?archive=zip\" download>Download as ZIP</a>"));
+
+    let response = server.get("/project/?archive=zip");
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Content-Type"), Some("application/zip"));
+    assert_eq!(response.header("Transfer-Encoding"), Some("chunked"));
+    assert_eq!(
+        response.header("Content-Disposition"),
+        Some("attachment; filename=\"project.zip\"; filename*=UTF-8''project.zip")
+    );
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(response.body)).unwrap();
+    let mut names = archive.file_names().map(str::to_string).collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, ["empty/", "src/", "src/main.rs"]);
+    let main = archive.by_name("src/main.rs").unwrap();
+    let stamp = main.last_modified().unwrap();
+    assert_eq!((stamp.year(), stamp.month(), stamp.day(), stamp.hour(), stamp.minute()), (2023, 11, 14, 22, 13));
+    #[cfg(unix)]
+    assert_eq!(main.unix_mode().map(|mode| mode & 0o777), Some(0o755));
+
+    assert_eq!(server.get("/project/?archive=rar").status, 400);
+}
+
+#[test]
+fn test_directory_downloads_as_tar_gz() {
+    use std::io::Read;
+
+    let server = TestServer::start_temp();
+    fs::create_dir_all(server.root().join("project/src")).unwrap();
+    fs::write(server.root().join("project/src/main.rs"), "fn main() {}\n").unwrap();
+    fs::write(server.root().join("project/.env"), "SECRET=1").unwrap();
+    let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+    fs::File::options().write(true).open(server.root().join("project/src/main.rs")).unwrap().set_modified(modified).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(server.root().join("project/src/main.rs"), fs::Permissions::from_mode(0o750)).unwrap();
+        std::os::unix::fs::symlink("src", server.root().join("project/latest")).unwrap();
+    }
+
+    assert!(server.get("/").text().contains("<a href=\"/project/?archive=tar.gz\" download>tar.gz</a>"));
+    let response = server.get("/project/?archive=tar.gz");
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Content-Type"), Some("application/gzip"));
+    assert_eq!(
+        response.header("Content-Disposition"),
+        Some("attachment; filename=\"project.tar.gz\"; filename*=UTF-8''project.tar.gz")
+    );
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(std::io::Cursor::new(response.body)));
+    let mut entries = Vec::new();
+    for entry in archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let header = entry.header().clone();
+        let mut contents = String::new();
+        entry.read_to_string(&mut contents).unwrap();
+        let link = entry.link_name().unwrap().map(|target| target.to_string_lossy().into_owned());
+        entries.push((entry.path().unwrap().to_string_lossy().into_owned(), header, contents, link));
+    }
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    let names = entries.iter().map(|entry| entry.0.as_str()).collect::<Vec<_>>();
+    #[cfg(unix)]
+    assert_eq!(names, ["latest", "src/", "src/main.rs"]);
+    #[cfg(not(unix))]
+    assert_eq!(names, ["src/", "src/main.rs"]);
+
+    let (_, main, contents, _) = entries.iter().find(|entry| entry.0 == "src/main.rs").unwrap();
+    assert_eq!(contents, "fn main() {}\n");
+    assert_eq!(main.mtime().unwrap(), 1_700_000_000);
+    #[cfg(unix)]
+    {
+        assert_eq!(main.mode().unwrap(), 0o750);
+        let (_, latest, _, target) = &entries[0];
+        assert!(latest.entry_type().is_symlink());
+        assert_eq!(target.as_deref(), Some("src"));
+    }
+
+    let response = server.get("/project/?archive=tar");
+    assert_eq!(response.header("Content-Type"), Some("application/x-tar"));
+    assert_eq!(tar::Archive::new(std::io::Cursor::new(response.body)).entries().unwrap().count(), names.len());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_tar_links_stay_inside_the_archive() {
+    use std::io::Read;
+    let server = TestServer::start_temp();
+    fs::create_dir_all(server.root().join("project/src")).unwrap();
+    fs::create_dir_all(server.root().join("shared")).unwrap();
+    fs::write(server.root().join("project/readme.txt"), "readme").unwrap();
+    fs::write(server.root().join("shared/lib.rs"), "pub fn shared() {}\n").unwrap();
+    fs::write(server.root().join("notes.txt"), "notes").unwrap();
+    std::os::unix::fs::symlink(server.root().join("project/src"), server.root().join("project/code")).unwrap();
+    std::os::unix::fs::symlink(server.root().join("project/readme.txt"), server.root().join("project/src/readme")).unwrap();
+    std::os::unix::fs::symlink("../shared", server.root().join("project/common")).unwrap();
+    std::os::unix::fs::symlink(server.root().join("notes.txt"), server.root().join("project/notes.txt")).unwrap();
+
+    let response = server.get("/project/?archive=tar");
+    assert_eq!(response.status, 200);
+    let mut archive = tar::Archive::new(std::io::Cursor::new(response.body));
+    let mut entries = Vec::new();
+    for entry in archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let mut contents = String::new();
+        entry.read_to_string(&mut contents).unwrap();
+        let link = entry.link_name().unwrap().map(|target| target.to_string_lossy().into_owned());
+        entries.push((entry.path().unwrap().to_string_lossy().into_owned(), contents, link));
+    }
+    entries.sort();
+    let entry = |name: &str, contents: &str, link: Option<&str>| (name.to_string(), contents.to_string(), link.map(str::to_string));
+    assert_eq!(
+        entries,
+        [
+            // Absolute links inside the directory are stored relative to the link.
+            entry("code", "", Some("src")),
+            // Links out of the directory are packed as what they point to.
+            entry("common/", "", None),
+            entry("common/lib.rs", "pub fn shared() {}\n", None),
+            entry("notes.txt", "notes", None),
+            entry("readme.txt", "readme", None),
+            entry("src/", "", None),
+            entry("src/readme", "", Some("../readme.txt")),
+        ]
+    );
+}
+
+#[test]
+fn test_selected_entries_download_as_one_archive() {
+    let server = TestServer::start_temp();
+    fs::create_dir_all(server.root().join("photos/trip")).unwrap();
+    for name in ["a.jpg", "b.jpg", "my photo.jpg", "trip/c.jpg", ".hidden.jpg"] {
+        fs::write(server.root().join("photos").join(name), name).unwrap();
+    }
+
+    let listing = server.get("/photos/").text();
+    assert!(listing.contains("<form id=\"selection\" class=\"filter\" method=\"post\">"));
+    assert!(listing.contains("<input type=\"checkbox\" name=\"path\" value=\"a.jpg\" form=\"selection\""));
+
+    let response = server.post("/photos/", "archive=zip&path=a.jpg&path=my+photo.jpg&path=trip");
+    assert_eq!(response.status, 200);
+    assert_eq!(
+        response.header("Content-Disposition"),
+        Some("attachment; filename=\"photos.zip\"; filename*=UTF-8''photos.zip")
+    );
+    let archive = zip::ZipArchive::new(std::io::Cursor::new(response.body)).unwrap();
+    let mut names = archive.file_names().map(str::to_string).collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, ["a.jpg", "my photo.jpg", "trip/", "trip/c.jpg"]);
+
+    // A form longer than one read of the connection.
+    let many = "&path=b.jpg".repeat(1000);
+    let response = server.post("/photos/", &format!("archive=tar{}", many));
+    assert_eq!(response.status, 200);
+    let mut archive = tar::Archive::new(std::io::Cursor::new(response.body));
+    assert_eq!(archive.entries().unwrap().count(), 1);
+
+    assert_eq!(server.post("/photos/", "archive=zip&path=../photos/a.jpg").status, 400);
+    assert_eq!(server.post("/photos/", "archive=zip&path=%2Fetc%2Fpasswd").status, 400);
+    assert_eq!(server.post("/photos/", "archive=zip&path=.hidden.jpg").status, 404);
+    assert_eq!(server.post("/photos/", "archive=zip&path=missing.jpg").status, 400);
+    assert_eq!(server.post("/photos/", "archive=zip").status, 400);
+    assert_eq!(server.post("/photos/", "archive=rar&path=a.jpg").status, 400);
+    #[cfg(unix)]
+    {
+        let outside = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink(outside.path(), server.root().join("photos/escape")).unwrap();
+        assert_eq!(server.post("/photos/", "archive=zip&path=escape").status, 403);
+    }
+}
@@ -0,0 +1,20 @@
+use std::io::Read;
+use std::path::Path;
+
+use rust_web_explorer::vfs::{FileSystem, MemoryFs};
+
+#[test]
+fn test_memory_fs_is_usable_outside_the_crate() {
+    let fs = MemoryFs::new();
+    fs.add_file("/srv/docs/readme.txt", "hello");
+    fs.add_dir("/srv/empty");
+
+    let fs: &dyn FileSystem = &fs;
+    assert!(fs.metadata(Path::new("/srv/docs")).unwrap().is_dir());
+    let mut names = fs.read_dir(Path::new("/srv")).unwrap().collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, [Path::new("/srv/docs"), Path::new("/srv/empty")]);
+    let mut contents = String::new();
+    fs.open(Path::new("/srv/docs/readme.txt")).unwrap().read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "hello");
+}